@@ -0,0 +1,275 @@
+use atty::Stream;
+use clap::{Arg, ArgMatches, Command};
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+mod vault;
+
+use crate::chat::budget::{print_budgets, statuses_for};
+use crate::chat::config::{load_config, resolve_api_key, save_env_var, DEFAULT_API_KEY_VAR};
+use crate::chat::credentials::{
+    credentials_path, endpoint_host, mask_key, Credential, Credentials,
+};
+use crate::chat::parser::{chat_from_config, parse_file, ParsedChat};
+use crate::chat::vault::{agent_running, vault_exists, vault_path, Vault};
+use crate::error::{Error, Result};
+
+/// Handles the 'auth' subcommand.
+pub fn handle_auth_subcommand(matches: &ArgMatches) -> Result<()> {
+    match matches.subcommand() {
+        Some(("login", login_m)) => login(login_m),
+        Some(("logout", logout_m)) => logout(logout_m),
+        Some(("status", status_m)) => status(status_m),
+        Some(("vault", vault_m)) => vault::handle_vault_subcommand(vault_m),
+        _ => {
+            println!("No valid auth subcommand was used");
+            Ok(())
+        }
+    }
+}
+
+/// Returns the settings of a chat file, or of the current directory if none is given.
+fn chat_settings(file: Option<&String>) -> Result<ParsedChat> {
+    match file {
+        Some(file) => parse_file(file),
+        None => chat_from_config(&load_config()?),
+    }
+}
+
+fn load_credentials() -> Result<Credentials> {
+    Credentials::load().map_err(|e| Error::io("Failed to read the credential store", e))
+}
+
+fn login(matches: &ArgMatches) -> Result<()> {
+    let chat = chat_settings(None)?;
+    let host = matches
+        .get_one::<String>("endpoint")
+        .map(|endpoint| endpoint_host(endpoint))
+        .unwrap_or_else(|| endpoint_host(&chat.api_endpoint));
+
+    let api_key = match matches.get_one::<String>("key") {
+        Some(key) => key.clone(),
+        None => read_key(&host).map_err(|e| Error::io("Failed to read the API key", e))?,
+    };
+    if api_key.is_empty() {
+        return Err(Error::Usage("No API key given.".to_string()));
+    }
+
+    if matches.get_flag("env") {
+        let variable = chat.api_key_env.as_deref().unwrap_or(DEFAULT_API_KEY_VAR);
+        let path =
+            save_env_var(variable, &api_key).map_err(|e| Error::io("Failed to save API key", e))?;
+        println!("Saved {} in {}", variable, path.display());
+        return Ok(());
+    }
+
+    let name = matches
+        .get_one::<String>("name")
+        .cloned()
+        .unwrap_or_else(|| host.clone());
+    let credential = Credential {
+        host: host.clone(),
+        api_key,
+    };
+    // Once a vault exists, new keys go into it instead of the plain-text store
+    let saved = match open_vault()? {
+        Some(mut vault) => {
+            vault.credentials.keys.insert(name.clone(), credential);
+            vault.save()
+        }
+        None => Credentials::load().and_then(|mut credentials| {
+            credentials.keys.insert(name.clone(), credential);
+            credentials.save()
+        }),
+    };
+    let path = saved.map_err(|e| Error::io("Failed to save API key", e))?;
+    println!("Saved key '{}' for {} in {}", name, host, path.display());
+    Ok(())
+}
+
+/// Reads a key from stdin, prompting for it on a terminal.
+fn read_key(host: &str) -> io::Result<String> {
+    if atty::is(Stream::Stdin) {
+        eprint!("API key for {}: ", host);
+        io::stderr().flush()?;
+    }
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim().to_string())
+}
+
+/// Opens the vault if one was created, asking for the passphrase if needed.
+fn open_vault() -> Result<Option<Vault>> {
+    Vault::open(true).map_err(|e| Error::Auth(format!("Failed to open the vault: {}", e)))
+}
+
+fn logout(matches: &ArgMatches) -> Result<()> {
+    let mut credentials = load_credentials()?;
+    let mut vault = open_vault()?;
+    let host = endpoint_host(&chat_settings(None)?.api_endpoint);
+
+    let mut removed = remove_keys(matches, &mut credentials, &host);
+    if let Some(vault) = &mut vault {
+        removed.extend(remove_keys(matches, &mut vault.credentials, &host));
+    }
+
+    if removed.is_empty() {
+        return Err(Error::Auth("No matching saved key.".to_string()));
+    }
+    credentials
+        .save()
+        .and_then(|_| {
+            vault
+                .as_ref()
+                .map_or(Ok(()), |vault| vault.save().map(|_| ()))
+        })
+        .map_err(|e| Error::io("Failed to update the saved keys", e))?;
+    for name in removed {
+        println!("Removed key '{}'", name);
+    }
+    Ok(())
+}
+
+/// Removes the keys `logout` asks for from one store and returns their names.
+fn remove_keys(matches: &ArgMatches, credentials: &mut Credentials, host: &str) -> Vec<String> {
+    if matches.get_flag("all") {
+        return std::mem::take(&mut credentials.keys).into_keys().collect();
+    }
+    let name = match matches.get_one::<String>("name") {
+        Some(name) => Some(name.clone()),
+        None => credentials
+            .find(None, host)
+            .map(|(name, _)| name.to_string()),
+    };
+    name.and_then(|name| credentials.keys.remove(&name).map(|_| name))
+        .into_iter()
+        .collect()
+}
+
+/// Shows which key a chat (or the current directory) would use, and the saved keys.
+fn status(matches: &ArgMatches) -> Result<()> {
+    let chat = chat_settings(matches.get_one::<String>("file"))?;
+    let active = resolve_api_key(None, &chat);
+
+    println!("Endpoint:  {}", chat.api_endpoint);
+    if let Some(profile) = &chat.profile {
+        println!("Profile:   {}", profile);
+    }
+    match &active {
+        Some(api_key) => println!(
+            "Key:       {} (from {})",
+            mask_key(&api_key.key),
+            api_key.origin
+        ),
+        None => println!("Key:       none found; run `samvada auth login`"),
+    }
+
+    let credentials = load_credentials()?;
+    let path = credentials_path()
+        .map(|path| path.display().to_string())
+        .unwrap_or_default();
+    print_keys(&format!("Saved keys ({})", path), &credentials);
+
+    if vault_exists() {
+        let path = vault_path()
+            .map(|path| path.display().to_string())
+            .unwrap_or_default();
+        // Only list the vault's keys when that needs no passphrase
+        match Vault::open(false)
+            .ok()
+            .flatten()
+            .filter(|_| agent_running())
+        {
+            Some(vault) => print_keys(
+                &format!("Vault keys ({}, unlocked)", path),
+                &vault.credentials,
+            ),
+            None => println!("\nVault:     {} (locked)", path),
+        }
+    }
+
+    let chat_file = matches.get_one::<String>("file").map(Path::new);
+    print_budgets(&statuses_for(chat_file, chat.profile.as_deref())?);
+
+    match active {
+        Some(_) => Ok(()),
+        None => Err(Error::Auth("No API key found.".to_string())),
+    }
+}
+
+fn print_keys(title: &str, credentials: &Credentials) {
+    if credentials.keys.is_empty() {
+        return;
+    }
+    println!("\n{}:", title);
+    let width = credentials.keys.keys().map(String::len).max().unwrap_or(0);
+    for (name, credential) in &credentials.keys {
+        println!(
+            "  {:<width$}  {}  {}",
+            name,
+            credential.host,
+            mask_key(&credential.api_key)
+        );
+    }
+}
+
+/// Defines the 'auth' command for managing API keys.
+pub fn auth_command() -> Command {
+    Command::new("auth")
+        .about("Manage API keys")
+        .subcommand(
+            Command::new("login")
+                .about("Save an API key in the vault, or in ~/.samvada/credentials without one")
+                .arg(
+                    Arg::new("key")
+                        .long("key")
+                        .help("The API key; read from stdin if omitted")
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new("name")
+                        .long("name")
+                        .help("Name of the key (default: the endpoint host)")
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new("endpoint")
+                        .long("endpoint")
+                        .help("Endpoint URL or host the key is for (default: the configured endpoint)")
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new("env")
+                        .long("env")
+                        .help("Save the key in the .env file instead, keeping its other lines")
+                        .conflicts_with("name")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("logout")
+                .about("Remove a saved API key")
+                .arg(
+                    Arg::new("name")
+                        .help("Name of the key (default: the key for the configured endpoint)")
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new("all")
+                        .long("all")
+                        .help("Remove every saved key")
+                        .conflicts_with("name")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("status")
+                .about("Show which API key is used and where it comes from")
+                .arg(
+                    Arg::new("file")
+                        .help("Chat file to check (default: the current directory's settings)")
+                        .num_args(1),
+                ),
+        )
+        .subcommand(vault::vault_command())
+}
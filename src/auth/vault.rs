@@ -0,0 +1,297 @@
+use clap::{Arg, ArgMatches, Command};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::chat::config::{
+    env_file_value, get_env_file_path, load_config, parse_duration, remove_env_var,
+    DEFAULT_API_KEY_VAR,
+};
+use crate::chat::credentials::{endpoint_host, Credential, Credentials};
+use crate::chat::vault::{
+    agent_running, read_passphrase, serve_agent, start_agent, stop_agent, vault_exists, vault_path,
+    Vault, PASSPHRASE_VAR,
+};
+use crate::error::{Error, Result};
+
+/// Environment variable that can hold the new passphrase for `vault passwd`.
+const NEW_PASSPHRASE_VAR: &str = "SAMVADA_VAULT_NEW_PASSPHRASE";
+
+/// Handles the 'auth vault' subcommand.
+pub fn handle_vault_subcommand(matches: &ArgMatches) -> Result<()> {
+    match matches.subcommand() {
+        Some(("init", _)) => init(),
+        Some(("unlock", unlock_m)) => unlock(unlock_m),
+        Some(("lock", _)) => {
+            lock();
+            Ok(())
+        }
+        Some(("passwd", _)) => passwd(),
+        Some(("import", import_m)) => import(import_m),
+        Some(("agent", agent_m)) => agent(agent_m),
+        _ => {
+            println!("No valid vault subcommand was used");
+            Ok(())
+        }
+    }
+}
+
+fn no_vault() -> Error {
+    Error::Auth("There is no vault; create one with `samvada auth vault init`.".to_string())
+}
+
+fn no_passphrase(variable: &str) -> Error {
+    Error::Auth(format!(
+        "No passphrase given; run this in a terminal or set {}.",
+        variable
+    ))
+}
+
+/// Reads the current passphrase, failing if there is no way to ask for it.
+fn current_passphrase() -> Result<String> {
+    read_passphrase("Vault passphrase: ")
+        .map_err(|e| Error::io("Failed to read the passphrase", e))?
+        .ok_or_else(|| no_passphrase(PASSPHRASE_VAR))
+}
+
+/// Reads a new passphrase from `variable`, else asks for it twice on the terminal.
+fn new_passphrase(variable: &str) -> Result<String> {
+    let passphrase = match std::env::var(variable) {
+        Ok(passphrase) => passphrase,
+        Err(_) if atty::is(atty::Stream::Stdin) => {
+            let read = |prompt| {
+                rpassword::prompt_password(prompt)
+                    .map_err(|e| Error::io("Failed to read the passphrase", e))
+            };
+            let passphrase = read("New vault passphrase: ")?;
+            if read("Repeat the passphrase: ")? != passphrase {
+                return Err(Error::Usage("The passphrases do not match.".to_string()));
+            }
+            passphrase
+        }
+        Err(_) => return Err(no_passphrase(variable)),
+    };
+    if passphrase.is_empty() {
+        return Err(Error::Usage(
+            "The passphrase must not be empty.".to_string(),
+        ));
+    }
+    Ok(passphrase)
+}
+
+fn init() -> Result<()> {
+    let path = vault_path()?;
+    if vault_exists() {
+        return Err(Error::Usage(format!(
+            "A vault already exists at {}.",
+            path.display()
+        )));
+    }
+    let passphrase = new_passphrase(PASSPHRASE_VAR)?;
+    Vault::create(&passphrase, Credentials::default())
+        .map_err(|e| Error::io("Failed to create the vault", e))?;
+    println!("Created the vault at {}", path.display());
+    println!("Run `samvada auth vault import` to move your existing keys into it.");
+    Ok(())
+}
+
+/// Opens the vault with its passphrase, failing if there is none.
+fn unlock_vault() -> Result<Vault> {
+    if !vault_exists() {
+        return Err(no_vault());
+    }
+    Vault::unlock(&current_passphrase()?)
+        .map_err(|e| Error::Auth(format!("Failed to unlock the vault: {}", e)))
+}
+
+fn unlock(matches: &ArgMatches) -> Result<()> {
+    let timeout = matches.get_one::<String>("timeout").unwrap();
+    let duration = parse_duration(timeout).ok_or_else(|| {
+        Error::Usage(format!(
+            "Invalid timeout '{}'; use e.g. 90s, 15m or 2h.",
+            timeout
+        ))
+    })?;
+    let vault = unlock_vault()?;
+    start_agent(vault.key(), duration)
+        .map_err(|e| Error::io("Failed to cache the unlocked vault", e))?;
+    println!("Vault unlocked for {}", timeout);
+    Ok(())
+}
+
+fn lock() {
+    if stop_agent() {
+        println!("Vault locked");
+    } else {
+        println!("The vault was not unlocked");
+    }
+}
+
+fn passwd() -> Result<()> {
+    let mut vault = unlock_vault()?;
+    let passphrase = new_passphrase(NEW_PASSPHRASE_VAR)?;
+    let was_unlocked = agent_running();
+    let path = vault
+        .rotate(&passphrase)
+        .map_err(|e| Error::io("Failed to change the passphrase", e))?;
+    println!("Changed the passphrase of {}", path.display());
+    // The cached key belongs to the old passphrase
+    if was_unlocked && stop_agent() {
+        println!("Vault locked; run `samvada auth vault unlock` with the new passphrase");
+    }
+    Ok(())
+}
+
+/// Moves keys from a .env file and the plain-text credential store into the vault.
+fn import(matches: &ArgMatches) -> Result<()> {
+    let env_path = match matches.get_one::<String>("env-file") {
+        Some(path) => PathBuf::from(path),
+        None => get_env_file_path()?,
+    };
+    let remove = matches.get_flag("remove");
+    let config = load_config()?;
+    let mut vault = Vault::open(true)
+        .map_err(|e| Error::Auth(format!("Failed to open the vault: {}", e)))?
+        .ok_or_else(no_vault)?;
+
+    // Each variable is saved under the name and endpoint host that would read it
+    let mut variables = vec![(
+        DEFAULT_API_KEY_VAR.to_string(),
+        endpoint_host(&config.api_endpoint),
+        endpoint_host(&config.api_endpoint),
+    )];
+    for (name, profile) in &config.profiles {
+        if let Some(variable) = &profile.api_key_env {
+            let endpoint = profile
+                .api_endpoint
+                .as_ref()
+                .unwrap_or(&config.api_endpoint);
+            let key_name = profile.credential.clone().unwrap_or_else(|| name.clone());
+            variables.push((variable.clone(), key_name, endpoint_host(endpoint)));
+        }
+    }
+
+    // Keys saved by name come first; a key whose name the vault already
+    // uses for a different key is skipped and left where it is
+    let mut credentials =
+        Credentials::load().map_err(|e| Error::io("Failed to read the credential store", e))?;
+    let mut candidates: Vec<(String, Credential, Option<String>)> = credentials
+        .keys
+        .iter()
+        .map(|(name, credential)| (name.clone(), credential.clone(), None))
+        .collect();
+    let env = fs::read_to_string(&env_path).unwrap_or_default();
+    for (variable, name, host) in variables {
+        if let Some(api_key) = env_file_value(&env, &variable) {
+            candidates.push((name, Credential { host, api_key }, Some(variable)));
+        }
+    }
+
+    let mut imported: Vec<(String, Option<String>)> = Vec::new();
+    for (name, credential, variable) in candidates {
+        let source = match &variable {
+            Some(variable) => format!("{} in {}", variable, env_path.display()),
+            None => "the credential store".to_string(),
+        };
+        match vault.credentials.keys.get(&name) {
+            Some(existing) if existing.api_key != credential.api_key => {
+                println!(
+                    "Skipped '{}' from {}: the vault already has a different key with that name",
+                    name, source
+                );
+                continue;
+            }
+            Some(_) => {}
+            None => {
+                vault.credentials.keys.insert(name.clone(), credential);
+            }
+        }
+        println!("Imported '{}' from {}", name, source);
+        imported.push((name, variable));
+    }
+
+    if imported.is_empty() {
+        println!("Found no keys to import");
+        return Ok(());
+    }
+    let path = vault
+        .save()
+        .map_err(|e| Error::io("Failed to save the vault", e))?;
+    println!("Saved the vault at {}", path.display());
+
+    if !remove {
+        println!("The plain-text copies were kept; use --remove to delete them");
+        return Ok(());
+    }
+    for (name, variable) in &imported {
+        match variable {
+            Some(variable) => {
+                remove_env_var(&env_path, variable).map_err(|e| {
+                    Error::io(
+                        format!("Failed to remove {} from {}", variable, env_path.display()),
+                        e,
+                    )
+                })?;
+            }
+            None => {
+                credentials.keys.remove(name);
+            }
+        }
+    }
+    credentials
+        .save()
+        .map_err(|e| Error::io("Failed to update the credential store", e))?;
+    println!("Removed the imported plain-text keys");
+    Ok(())
+}
+
+fn agent(matches: &ArgMatches) -> Result<()> {
+    let seconds = *matches.get_one::<u64>("timeout").unwrap();
+    serve_agent(Duration::from_secs(seconds)).map_err(|e| Error::io("Vault agent failed", e))
+}
+
+/// Defines the 'auth vault' command for the encrypted key vault.
+pub fn vault_command() -> Command {
+    Command::new("vault")
+        .about("Keep API keys in a passphrase-encrypted vault")
+        .subcommand(Command::new("init").about("Create an empty vault in ~/.samvada/vault"))
+        .subcommand(
+            Command::new("unlock")
+                .about("Cache the unlocked vault for a while, so the passphrase isn't asked for again")
+                .arg(
+                    Arg::new("timeout")
+                        .long("timeout")
+                        .help("How long to stay unlocked, e.g. 90s, 15m or 2h")
+                        .default_value("15m")
+                        .num_args(1),
+                ),
+        )
+        .subcommand(Command::new("lock").about("Forget the cached unlocked vault"))
+        .subcommand(Command::new("passwd").about("Change the vault's passphrase"))
+        .subcommand(
+            Command::new("import")
+                .about("Move keys from the .env file and the plain-text credential store into the vault")
+                .arg(
+                    Arg::new("env-file")
+                        .long("env-file")
+                        .help("The .env file to import from (default: the one samvada reads)")
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new("remove")
+                        .long("remove")
+                        .help("Delete the imported keys from the .env file and the credential store")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("agent")
+                .hide(true)
+                .arg(
+                    Arg::new("timeout")
+                        .long("timeout")
+                        .required(true)
+                        .value_parser(clap::value_parser!(u64)),
+                ),
+        )
+}
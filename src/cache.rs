@@ -0,0 +1,91 @@
+use clap::{Arg, ArgAction, ArgMatches, Command};
+
+use crate::chat::cache::ResponseCache;
+use crate::chat::config::load_config;
+use crate::error::{Error, Result};
+
+/// Handles the 'cache' command for the response cache.
+pub fn handle_cache_subcommand(matches: &ArgMatches) -> Result<()> {
+    let config = load_config()?;
+    let cache = ResponseCache::open(&config.cache, false)
+        .ok_or_else(|| Error::Config("The response cache can't be opened.".to_string()))?;
+
+    match matches.subcommand() {
+        Some(("stats", _)) => stats(&cache, config.cache.enabled),
+        Some(("clear", clear_m)) => {
+            let removed = cache
+                .clear(clear_m.get_flag("expired"))
+                .map_err(|e| Error::io("Failed to clear the cache", e))?;
+            println!(
+                "Removed {} cached answers from {}",
+                removed,
+                cache.dir().display()
+            );
+            Ok(())
+        }
+        _ => Err(Error::Usage(
+            "Unknown cache subcommand. Use `samvada cache --help` for usage.".to_string(),
+        )),
+    }
+}
+
+fn stats(cache: &ResponseCache, enabled: bool) -> Result<()> {
+    let stats = cache
+        .stats()
+        .map_err(|e| Error::io("Failed to read the cache", e))?;
+    let time = |time: Option<chrono::DateTime<chrono::Local>>| {
+        time.map_or_else(
+            || "-".to_string(),
+            |time| time.format("%Y-%m-%d %H:%M").to_string(),
+        )
+    };
+
+    println!("Directory: {}", cache.dir().display());
+    println!(
+        "Enabled:   {}",
+        if enabled {
+            "yes"
+        } else {
+            "no (use --cache, or set cache.enabled)"
+        }
+    );
+    println!("Answers:   {} ({} expired)", stats.entries, stats.expired);
+    println!("Size:      {}", format_size(stats.size));
+    println!("Oldest:    {}", time(stats.oldest));
+    println!("Newest:    {}", time(stats.newest));
+    Ok(())
+}
+
+/// Formats a size in bytes as `512 B`, `1.5 KiB` or `3.2 MiB`.
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 3] = ["KiB", "MiB", "GiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64;
+    let mut unit = "B";
+    for next in UNITS {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = next;
+    }
+    format!("{:.1} {}", size, unit)
+}
+
+/// Defines the 'cache' command for inspecting and clearing the response cache.
+pub fn cache_command() -> Command {
+    Command::new("cache")
+        .about("Inspect or clear the response cache")
+        .subcommand_required(true)
+        .subcommand(Command::new("stats").about("Show how many answers are cached and their size"))
+        .subcommand(
+            Command::new("clear").about("Remove cached answers").arg(
+                Arg::new("expired")
+                    .long("expired")
+                    .help("Only remove answers older than the cache's ttl")
+                    .action(ArgAction::SetTrue),
+            ),
+        )
+}
@@ -1,31 +1,117 @@
-use chrono::{DateTime, Local, TimeZone};
+use chrono::{DateTime, FixedOffset, Local, TimeZone};
 use serde_json::Value;
+use std::collections::BTreeMap;
 use std::fs::OpenOptions;
 use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::chat::cache::CACHED_AT_FIELD;
+use crate::chat::cassette;
+use crate::chat::config::{load_config_for, AppConfig};
+use crate::chat::usage::estimate_cost;
+
+/// Format of the `created` metadata comment.
+pub const CREATED_FORMAT: &str = "%Y-%m-%d %H:%M:%S %:z";
 
 /// Common metadata structure for OpenAI responses
+///
+/// Written after each answer as `<!-- key: value -->` comments, and read back
+/// from them with `parser::parse_response_metadata`.
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct ResponseMetadata {
     pub model: String,
     pub id: String,
     pub created_formatted: String,
-    pub total_tokens: i64,
+    pub prompt_tokens: Option<u64>,
+    pub completion_tokens: Option<u64>,
+    pub total_tokens: u64,
+    /// Prompt tokens served from the provider's prompt cache.
+    pub cached_tokens: Option<u64>,
+    /// Completion tokens spent on hidden reasoning.
+    pub reasoning_tokens: Option<u64>,
+    pub finish_reason: Option<String>,
+    pub latency_ms: Option<u64>,
+    /// Estimated cost in US dollars, when the model has a price.
+    pub cost: Option<f64>,
+    /// When the answer was cached, if it came from the response cache.
+    pub from_cache: Option<String>,
 }
 
 impl ResponseMetadata {
-    pub fn from_response(response_body: &Value) -> Self {
+    pub fn from_response(
+        response_body: &Value,
+        latency: Option<Duration>,
+        config: Option<&AppConfig>,
+    ) -> Self {
         let created = response_body["created"].as_i64().unwrap_or_default();
         let created_datetime: DateTime<Local> = Local
             .timestamp_opt(created, 0)
             .single()
-            .unwrap_or_else(|| Local::now());
-        
+            .unwrap_or_else(Local::now);
+        let usage = &response_body["usage"];
+        let model = response_body["model"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        let prompt_tokens = usage["prompt_tokens"].as_u64();
+        let completion_tokens = usage["completion_tokens"].as_u64();
+        // Providers that don't support the details send zeros or leave them out
+        let nonzero = |value: &Value| value.as_u64().filter(|&n| n > 0);
+        let from_cache = response_body[CACHED_AT_FIELD]
+            .as_str()
+            .and_then(|cached_at| DateTime::parse_from_rfc3339(cached_at).ok())
+            .map(|cached_at| cached_at.format(CREATED_FORMAT).to_string());
+
         Self {
-            model: response_body["model"].as_str().unwrap_or_default().to_string(),
+            // A cached answer was paid for when it was first received
+            cost: config
+                .filter(|_| from_cache.is_none())
+                .and_then(|config| estimate_cost(config, &model, prompt_tokens, completion_tokens)),
+            model,
             id: response_body["id"].as_str().unwrap_or_default().to_string(),
-            created_formatted: created_datetime.format("%Y-%m-%d %H:%M:%S %:z").to_string(),
-            total_tokens: response_body["usage"]["total_tokens"].as_i64().unwrap_or_default(),
+            created_formatted: created_datetime.format(CREATED_FORMAT).to_string(),
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: usage["total_tokens"].as_u64().unwrap_or_default(),
+            cached_tokens: nonzero(&usage["prompt_tokens_details"]["cached_tokens"]),
+            reasoning_tokens: nonzero(&usage["completion_tokens_details"]["reasoning_tokens"]),
+            finish_reason: response_body["choices"][0]["finish_reason"]
+                .as_str()
+                .map(str::to_string),
+            latency_ms: latency.map(|latency| latency.as_millis() as u64),
+            from_cache,
         }
     }
+
+    /// Builds the metadata from the comments after one answer, as returned by
+    /// `parser::parse_metadata_comments`. Missing or invalid values are left empty.
+    pub fn from_comments(comments: &BTreeMap<String, String>) -> Self {
+        let text = |key: &str| comments.get(key).cloned().unwrap_or_default();
+        let number = |key: &str| comments.get(key).and_then(|value| value.parse().ok());
+
+        Self {
+            model: text("model"),
+            id: text("id"),
+            created_formatted: text("created"),
+            prompt_tokens: number("prompt_tokens"),
+            completion_tokens: number("completion_tokens"),
+            total_tokens: number("total_tokens").unwrap_or_default(),
+            cached_tokens: number("cached_tokens"),
+            reasoning_tokens: number("reasoning_tokens"),
+            finish_reason: comments.get("finish_reason").cloned(),
+            latency_ms: number("latency_ms"),
+            cost: comments
+                .get("cost")
+                .and_then(|value| value.trim_start_matches('$').parse().ok()),
+            from_cache: comments.get("from_cache").cloned(),
+        }
+    }
+
+    /// The time the answer was created, if `created` is a valid timestamp.
+    pub fn created(&self) -> Option<DateTime<FixedOffset>> {
+        DateTime::parse_from_str(&self.created_formatted, CREATED_FORMAT).ok()
+    }
 }
 
 /// Writes metadata comments to a file
@@ -33,7 +119,31 @@ pub fn write_metadata(file: &mut std::fs::File, metadata: &ResponseMetadata) ->
     writeln!(file, "<!-- model: {} -->", metadata.model)?;
     writeln!(file, "<!-- id: {} -->", metadata.id)?;
     writeln!(file, "<!-- created: {} -->", metadata.created_formatted)?;
+    if let Some(prompt_tokens) = metadata.prompt_tokens {
+        writeln!(file, "<!-- prompt_tokens: {} -->", prompt_tokens)?;
+    }
+    if let Some(completion_tokens) = metadata.completion_tokens {
+        writeln!(file, "<!-- completion_tokens: {} -->", completion_tokens)?;
+    }
     writeln!(file, "<!-- total_tokens: {} -->", metadata.total_tokens)?;
+    if let Some(cached_tokens) = metadata.cached_tokens {
+        writeln!(file, "<!-- cached_tokens: {} -->", cached_tokens)?;
+    }
+    if let Some(reasoning_tokens) = metadata.reasoning_tokens {
+        writeln!(file, "<!-- reasoning_tokens: {} -->", reasoning_tokens)?;
+    }
+    if let Some(finish_reason) = &metadata.finish_reason {
+        writeln!(file, "<!-- finish_reason: {} -->", finish_reason)?;
+    }
+    if let Some(latency_ms) = metadata.latency_ms {
+        writeln!(file, "<!-- latency_ms: {} -->", latency_ms)?;
+    }
+    if let Some(cost) = metadata.cost {
+        writeln!(file, "<!-- cost: {:.6} -->", cost)?;
+    }
+    if let Some(from_cache) = &metadata.from_cache {
+        writeln!(file, "<!-- from_cache: {} -->", from_cache)?;
+    }
     Ok(())
 }
 
@@ -43,9 +153,9 @@ pub fn handle_openai_response(
     question: Option<&str>,
     answer: &str,
     response_body: &Value,
+    latency: Duration,
 ) -> std::io::Result<()> {
     let mut file = OpenOptions::new()
-        .write(true)
         .append(true)
         .open(file_path)?;
 
@@ -55,7 +165,11 @@ pub fn handle_openai_response(
     
     writeln!(file, "assistant:\n{}\n", answer)?;
 
-    let metadata = ResponseMetadata::from_response(response_body);
+    // Priced with the config that applies to the chat
+    let config = load_config_for(Path::new(file_path)).ok();
+    // A replayed answer's latency means nothing, and would make each run's output differ
+    let latency = (!cassette::replaying()).then_some(latency);
+    let metadata = ResponseMetadata::from_response(response_body, latency, config.as_ref());
     write_metadata(&mut file, &metadata)?;
 
     if question.is_none() {
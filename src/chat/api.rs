@@ -1,6 +1,19 @@
-use log::{debug, info};
+use log::{debug, info, warn};
 use reqwest::Client;
-use serde_json::{json, to_string_pretty, Value};
+use serde_json::{json, Map, Value};
+
+use crate::chat::cache::{self, cache_key};
+use crate::chat::cassette::{self, Mode};
+use crate::chat::request_log::RequestRecord;
+use crate::error::{Error, Result};
+
+/// Builds the request body: model and messages, plus any extra parameters.
+pub fn build_request_body(model: &str, messages: Vec<Value>, params: &Map<String, Value>) -> Value {
+    let mut body = params.clone();
+    body.insert("model".to_string(), json!(model));
+    body.insert("messages".to_string(), Value::Array(messages));
+    Value::Object(body)
+}
 
 /// Queries the OpenAI API with the provided API key, model, and messages, returning the answer and response.
 pub async fn query_openai(
@@ -8,48 +21,236 @@ pub async fn query_openai(
     model: &str,
     api_endpoint: &str,
     messages: Vec<Value>,
-) -> Result<(String, Value), Box<dyn std::error::Error>> {
-    let client = Client::new();
+    params: &Map<String, Value>,
+) -> Result<(String, Value)> {
+    let body = build_request_body(model, messages, params);
+
+    let cache = cache::for_request(&body);
+    let cache_key = cache_key(api_endpoint, &body);
+    if let Some(response_body) = cache.and_then(|cache| cache.get(&cache_key)) {
+        info!("Answering from the cache ({})", cache_key);
+        let answer = response_body["choices"][0]["message"]["content"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        return Ok((answer, response_body));
+    }
 
     info!("Sending request to OpenAI API using model: {}", model);
-    debug!(
-        "Request payload:\n{}",
-        to_string_pretty(&json!({
-            "model": model,
-            "messages": messages
-        }))?
+    let record = RequestRecord::start(model, api_endpoint, &body);
+    debug!("Request hash: {}", record.hash());
+
+    let cassette = cassette::active();
+    let sent = match cassette {
+        Some(cassette) if cassette.mode == Mode::Replay => {
+            info!("Replaying the response from {}", cassette.path.display());
+            cassette.replay(api_endpoint, &body)
+        }
+        _ => send_request(api_key, api_endpoint, &body).await,
+    };
+    let (status, response_text) = match sent {
+        Ok(response) => response,
+        Err(e) => {
+            record.finish(api_key, None, None, Some(&e));
+            return Err(e);
+        }
+    };
+    if let Some(cassette) = cassette.filter(|cassette| cassette.mode == Mode::Record) {
+        cassette.record(api_key, api_endpoint, &body, status, &response_text)?;
+    }
+    let result = parse_response(status, &response_text);
+    record.finish(
+        api_key,
+        Some(status),
+        Some(&response_text),
+        result.as_ref().err(),
     );
+    let (answer, response_body) = result?;
+    if let Some(cache) = cache {
+        if let Err(e) = cache.put(&cache_key, api_endpoint, &response_body) {
+            warn!(
+                "Failed to cache the answer in {}: {}",
+                cache.dir().display(),
+                e
+            );
+        }
+    }
+
+    info!("Successfully received and parsed answer from OpenAI API");
+
+    Ok((answer, response_body))
+}
+
+/// Like `query_openai`, but streams the answer, passing each piece to `on_delta` as it arrives.
+///
+/// Requests answered by a cassette or the response cache aren't streamed;
+/// their whole answer is passed to `on_delta` at once. The returned response
+/// body has the same shape as a non-streamed one. If the endpoint answers 400,
+/// the request is sent once more without `stream_options`, and the response
+/// body's `usage` is then null.
+pub async fn stream_openai(
+    api_key: &str,
+    model: &str,
+    api_endpoint: &str,
+    messages: Vec<Value>,
+    params: &Map<String, Value>,
+    on_delta: &mut dyn FnMut(&str),
+) -> Result<(String, Value)> {
+    let body = build_request_body(model, messages.clone(), params);
+    if cassette::active().is_some() || cache::for_request(&body).is_some() {
+        let (answer, response_body) =
+            query_openai(api_key, model, api_endpoint, messages, params).await?;
+        on_delta(&answer);
+        return Ok((answer, response_body));
+    }
+
+    let mut body = body;
+    body["stream"] = json!(true);
+    // Without this, streamed responses don't report token usage
+    body["stream_options"] = json!({"include_usage": true});
+
+    let result = stream_request(api_key, model, api_endpoint, &body, on_delta).await;
+    if !matches!(result, Err(Error::Api { status: 400, .. })) {
+        return result;
+    }
+    // Some OpenAI-compatible servers reject fields they don't know
+    warn!("Streaming request was rejected; retrying without stream_options");
+    if let Some(body) = body.as_object_mut() {
+        body.remove("stream_options");
+    }
+    stream_request(api_key, model, api_endpoint, &body, on_delta).await
+}
+
+/// Sends one streaming request and records it in the request log.
+async fn stream_request(
+    api_key: &str,
+    model: &str,
+    api_endpoint: &str,
+    body: &Value,
+    on_delta: &mut dyn FnMut(&str),
+) -> Result<(String, Value)> {
+    info!("Streaming request to OpenAI API using model: {}", model);
+    let record = RequestRecord::start(model, api_endpoint, body);
+    debug!("Request hash: {}", record.hash());
+    match receive_stream(api_key, api_endpoint, body, on_delta).await {
+        Ok((status, answer, response_body)) => {
+            record.finish(
+                api_key,
+                Some(status),
+                Some(&response_body.to_string()),
+                None,
+            );
+            info!("Successfully received streamed answer from OpenAI API");
+            Ok((answer, response_body))
+        }
+        Err(e) => {
+            let status = match &e {
+                Error::Api { status, .. } => Some(*status),
+                _ => None,
+            };
+            record.finish(api_key, status, None, Some(&e));
+            Err(e)
+        }
+    }
+}
 
-    let response = client
+/// Posts a streaming request and reads its server-sent events, returning the
+/// status, the answer and a response body assembled from the chunks.
+async fn receive_stream(
+    api_key: &str,
+    api_endpoint: &str,
+    body: &Value,
+    on_delta: &mut dyn FnMut(&str),
+) -> Result<(u16, String, Value)> {
+    let mut response = Client::new()
         .post(api_endpoint)
         .header("Authorization", format!("Bearer {}", api_key))
-        .json(&json!({
-            "model": model,
-            "messages": messages
-        }))
+        .json(body)
         .send()
         .await?;
+    let status = response.status().as_u16();
+    if !(200..300).contains(&status) {
+        let text = response.text().await?;
+        return parse_response(status, &text).map(|(answer, body)| (status, answer, body));
+    }
 
-    let status = response.status();
-    let response_text = response.text().await?;
-
-    if !status.is_success() {
-        return Err(format!(
-            "OpenAI API error (status {}): {}",
-            status, response_text
-        ).into());
+    let mut answer = String::new();
+    let mut assembled = json!({"object": "chat.completion", "usage": null});
+    let mut finish_reason = Value::Null;
+    let mut buffer: Vec<u8> = Vec::new();
+    'events: while let Some(chunk) = response.chunk().await? {
+        buffer.extend_from_slice(&chunk);
+        while let Some(end) = buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let Some(data) = line.trim().strip_prefix("data:") else {
+                continue;
+            };
+            if data.trim() == "[DONE]" {
+                break 'events;
+            }
+            let Ok(event) = serde_json::from_str::<Value>(data.trim()) else {
+                continue;
+            };
+            for key in ["id", "model", "created"] {
+                if !event[key].is_null() {
+                    assembled[key] = event[key].clone();
+                }
+            }
+            if !event["usage"].is_null() {
+                assembled["usage"] = event["usage"].clone();
+            }
+            let choice = &event["choices"][0];
+            if let Some(delta) = choice["delta"]["content"].as_str() {
+                answer.push_str(delta);
+                on_delta(delta);
+            }
+            if !choice["finish_reason"].is_null() {
+                finish_reason = choice["finish_reason"].clone();
+            }
+        }
     }
 
-    let response_body: Value = serde_json::from_str(&response_text)?;
+    assembled["choices"] = json!([{
+        "index": 0,
+        "message": {"role": "assistant", "content": answer},
+        "finish_reason": finish_reason,
+    }]);
+    Ok((status, answer, assembled))
+}
 
-    debug!("Received response:\n{}", to_string_pretty(&response_body)?);
+/// Posts the request body, returning the status and body of the response.
+async fn send_request(api_key: &str, api_endpoint: &str, body: &Value) -> Result<(u16, String)> {
+    let response = Client::new()
+        .post(api_endpoint)
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(body)
+        .send()
+        .await?;
+
+    let status = response.status().as_u16();
+    Ok((status, response.text().await?))
+}
+
+/// Extracts the answer from a response, turning error statuses into errors.
+fn parse_response(status: u16, response_text: &str) -> Result<(String, Value)> {
+    let invalid = |message: String| Error::Api { status, message };
+    if !(200..300).contains(&status) {
+        // Prefer the message of an OpenAI-style `{"error": {"message": ...}}` body
+        let message = serde_json::from_str::<Value>(response_text)
+            .ok()
+            .and_then(|body| body["error"]["message"].as_str().map(str::to_string))
+            .unwrap_or_else(|| response_text.to_string());
+        return Err(invalid(message));
+    }
+
+    let response_body: Value = serde_json::from_str(response_text)
+        .map_err(|e| invalid(format!("the response is not valid JSON: {}", e)))?;
 
     let answer = response_body["choices"][0]["message"]["content"]
         .as_str()
-        .ok_or("Failed to extract answer from API response")?
+        .ok_or_else(|| invalid("the response has no answer".to_string()))?
         .to_string();
 
-    info!("Successfully received and parsed answer from OpenAI API");
-
     Ok((answer, response_body))
 }
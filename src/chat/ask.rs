@@ -1,28 +1,34 @@
 use clap::{Arg, ArgMatches, Command};
 use log::{debug, error, info};
 use serde_json::Value;
+use std::path::Path;
+use std::time::{Duration, Instant};
 
-use crate::chat::api::query_openai;
-use crate::chat::config::{get_api_key, get_env_file_path, save_api_key};
-use crate::chat::logging::setup_logging;
-use crate::chat::parser::{parse_file, prepare_api_messages};
 use crate::chat::_utils::handle_openai_response;
+use crate::chat::api::query_openai;
+use crate::chat::budget::check_budget;
+use crate::chat::cache::{self, CacheMode};
+use crate::chat::cassette;
+use crate::chat::config::{get_api_key, get_env_file_path, load_config_for, save_api_key};
+use crate::chat::credentials::endpoint_host;
+use crate::chat::dry_run::print_dry_run;
+use crate::chat::logging::{log_content, set_private, setup_logging};
+use crate::chat::parser::{parse_file, prepare_api_messages, ParsedChat};
+use crate::chat::usage;
+use crate::error::{Error, Result};
 
 /// Handles the 'ask' subcommand, processing the file and querying OpenAI
-pub async fn handle_ask_subcommand(matches: &ArgMatches) {
+pub async fn handle_ask_subcommand(matches: &ArgMatches) -> Result<()> {
     let file_path = matches.get_one::<String>("file").unwrap();
+    let dry_run = matches.get_one::<String>("dry-run");
 
-    // Setup logging FIRST
-    let _log_path = setup_logging(Some(file_path));
-    info!("Starting processing for file: {}", file_path);
-
-    // If API key is provided as argument, save it
-    if let Some(api_key) = matches.get_one::<String>("api-key") {
-        if let Err(e) = save_api_key(api_key) {
-            eprintln!("Failed to save API key: {}", e);
-            std::process::exit(1);
+    // Setup logging FIRST; a dry run leaves no files behind
+    if dry_run.is_none() {
+        if let Err(e) = setup_logging(Some(file_path)) {
+            eprintln!("Warning: {}; continuing without a log", e);
         }
     }
+    info!("Starting processing for file: {}", file_path);
 
     // Load environment variables from the config directory
     if let Ok(env_path) = get_env_file_path() {
@@ -34,43 +40,76 @@ pub async fn handle_ask_subcommand(matches: &ArgMatches) {
         dotenv::from_path(env_path).ok();
     }
 
-    let api_key = get_api_key(matches.get_one::<String>("api-key"));
+    let chat = parse_file(file_path)
+        .inspect_err(|e| error!("Error processing file and querying OpenAI: {}", e))?;
+    set_private(chat.private);
+    let config = load_config_for(Path::new(file_path))?;
 
-    match process_file_and_query_openai(file_path, &api_key).await {
-        Ok((answer, response_body)) => {
-            println!("Answer: {}", answer);
-            info!("Successfully processed file and received answer");
+    if let Some(format) = dry_run {
+        let api_messages = prepare_api_messages(&chat.system_prompt, &chat.messages);
+        print_dry_run(&chat, api_messages, format, &config);
+        return Ok(());
+    }
 
-            // Append the answer to the markdown file
-            if let Err(e) = append_answer_to_file(file_path, &answer, &response_body) {
-                error!("Failed to append answer to file: {}", e);
-                eprintln!("Failed to append answer to file: {}", e);
-            } else {
-                info!("Successfully appended answer to file");
-            }
-        }
-        Err(e) => {
-            error!("Error processing file and querying OpenAI: {}", e);
-            eprintln!("Error processing file and querying OpenAI: {}", e);
-            std::process::exit(1);
-        }
+    cache::init(&config.cache, CacheMode::from_matches(matches));
+
+    // If API key is provided as argument, save it for the chat's endpoint
+    if let Some(api_key) = matches.get_one::<String>("api-key") {
+        save_api_key(api_key, &endpoint_host(&chat.api_endpoint))?;
     }
+
+    // Replayed answers don't need a key, so cassettes can be used without one
+    let api_key = if cassette::replaying() {
+        String::new()
+    } else {
+        get_api_key(matches.get_one::<String>("api-key"), &chat)?
+    };
+
+    check_budget(
+        Some(Path::new(file_path)),
+        chat.profile.as_deref(),
+        matches.get_flag("override-budget"),
+    )?;
+
+    let started = Instant::now();
+    let (answer, response_body) = process_file_and_query_openai(&chat, &api_key)
+        .await
+        .inspect_err(|e| error!("Error processing file and querying OpenAI: {}", e))?;
+    let latency = started.elapsed();
+    usage::record(&chat, Some(Path::new(file_path)), &response_body, latency);
+    println!("Answer: {}", answer);
+    info!("Successfully processed file and received answer");
+
+    // Append the answer to the markdown file
+    append_answer_to_file(file_path, &answer, &response_body, latency)
+        .map_err(|e| Error::io("Failed to append answer to file", e))
+        .inspect_err(|e| error!("{}", e))?;
+    info!("Successfully appended answer to file");
+    Ok(())
 }
 
-/// Processes the file and queries OpenAI with the extracted information
+/// Queries OpenAI with the information parsed from the chat file
 async fn process_file_and_query_openai(
-    file_path: &str,
+    chat: &ParsedChat,
     api_key: &str,
-) -> Result<(String, Value), Box<dyn std::error::Error>> {
-    let (system_prompt, model, api_endpoint, messages) = parse_file(file_path)?;
-    let api_messages = prepare_api_messages(&system_prompt, &messages);
+) -> Result<(String, Value)> {
+    let api_messages = prepare_api_messages(&chat.system_prompt, &chat.messages);
 
-    debug!(
-        "Prepared API messages:\n{}",
-        serde_json::to_string_pretty(&api_messages)?
-    );
+    if log_content() {
+        debug!(
+            "Prepared API messages:\n{}",
+            serde_json::to_string_pretty(&api_messages).unwrap_or_default()
+        );
+    }
 
-    query_openai(api_key, &model, &api_endpoint, api_messages).await
+    query_openai(
+        api_key,
+        &chat.model,
+        &chat.api_endpoint,
+        api_messages,
+        &chat.params,
+    )
+    .await
 }
 
 /// Appends the answer and metadata to the specified file
@@ -78,8 +117,9 @@ fn append_answer_to_file(
     file_path: &str,
     answer: &str,
     response_body: &Value,
+    latency: Duration,
 ) -> std::io::Result<()> {
-    handle_openai_response(file_path, None, answer, response_body)
+    handle_openai_response(file_path, None, answer, response_body, latency)
 }
 
 /// Creates and returns the 'ask' command with its arguments
@@ -99,4 +139,39 @@ pub fn ask_command() -> Command {
                 .required(false)
                 .num_args(1),
         )
+        .arg(
+            Arg::new("override-budget")
+                .long("override-budget")
+                .help("Send the request even if a budget cap has been reached")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("cache")
+                .long("cache")
+                .help("Answer from the response cache if possible, even if it isn't enabled in the config")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("force-cache")
+                .long("force-cache")
+                .help("Use the response cache even when the temperature is above 0")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("no-cache")
+                .long("no-cache")
+                .help("Don't use the response cache")
+                .conflicts_with_all(["cache", "force-cache"])
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .help("Print the request and its estimated tokens and cost instead of sending it")
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_missing_value("json")
+                .value_parser(["json", "text"])
+                .value_name("FORMAT"),
+        )
 }
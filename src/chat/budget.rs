@@ -0,0 +1,228 @@
+//! Daily and monthly spending caps, checked against the usage ledger before each request.
+
+use chrono::{Datelike, Local, NaiveDate};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::chat::cassette;
+use crate::chat::config::{load_config_for, AppConfig, Budget};
+use crate::chat::logging::expand_home;
+use crate::chat::usage::{self, absolute, current_dir, project_dir, UsageEntry};
+use crate::error::{Error, Result};
+
+/// How much of one cap has been used.
+pub struct BudgetStatus {
+    /// `daily` or `monthly`.
+    pub period: &'static str,
+    /// What the cap applies to: `overall`, `profile work` or `project /path`.
+    pub scope: String,
+    /// Whether the cap is in US dollars rather than tokens.
+    pub is_cost: bool,
+    pub used: f64,
+    pub cap: f64,
+}
+
+impl BudgetStatus {
+    pub fn is_exceeded(&self) -> bool {
+        self.used >= self.cap
+    }
+
+    fn fraction(&self) -> f64 {
+        if self.cap > 0.0 {
+            self.used / self.cap
+        } else {
+            1.0
+        }
+    }
+
+    fn amount(&self, value: f64) -> String {
+        if self.is_cost {
+            format!("${:.2}", value)
+        } else {
+            format!("{} tokens", value as u64)
+        }
+    }
+
+    /// Describes the cap, e.g. "daily budget (overall): 850 tokens of 1000 tokens used, 150 tokens left".
+    pub fn describe(&self) -> String {
+        format!(
+            "{} budget ({}): {} of {} used, {} left",
+            self.period,
+            self.scope,
+            self.amount(self.used),
+            self.amount(self.cap),
+            self.amount((self.cap - self.used).max(0.0))
+        )
+    }
+}
+
+/// What a cap applies to.
+enum Scope {
+    Overall,
+    Profile(String),
+    /// The directory as written in `budgets.projects`, and where it resolves to.
+    Project(String, PathBuf),
+}
+
+impl Scope {
+    fn includes(&self, entry: &UsageEntry) -> bool {
+        match self {
+            Scope::Overall => true,
+            Scope::Profile(name) => entry.profile.as_deref() == Some(name.as_str()),
+            Scope::Project(_, dir) => {
+                entry.project.as_deref().map(Path::new) == Some(dir.as_path())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scope::Overall => write!(f, "overall"),
+            Scope::Profile(name) => write!(f, "profile {}", name),
+            Scope::Project(dir, _) => write!(f, "project {}", dir),
+        }
+    }
+}
+
+/// The caps that apply to requests made with `profile` from `project`, and how much of each is used.
+pub fn budget_statuses(
+    config: &AppConfig,
+    profile: Option<&str>,
+    project: Option<&Path>,
+    entries: &[UsageEntry],
+) -> Vec<BudgetStatus> {
+    let budgets = &config.budgets;
+    let overall = Budget {
+        daily: budgets.daily.clone(),
+        monthly: budgets.monthly.clone(),
+    };
+    let mut scopes = vec![(Scope::Overall, &overall)];
+    if let Some((name, budget)) = profile.and_then(|name| budgets.profiles.get_key_value(name)) {
+        scopes.push((Scope::Profile(name.clone()), budget));
+    }
+    if let Some(project) = project.map(absolute) {
+        for (dir, budget) in &budgets.projects {
+            if absolute(&expand_home(dir)) == project {
+                scopes.push((Scope::Project(dir.clone(), project.clone()), budget));
+            }
+        }
+    }
+
+    let today = Local::now().date_naive();
+    let mut statuses = Vec::new();
+    for (scope, budget) in &scopes {
+        for (period, limit) in [("daily", &budget.daily), ("monthly", &budget.monthly)] {
+            let Some(limit) = limit else {
+                continue;
+            };
+            let counted = entries.iter().filter(|entry| {
+                scope.includes(entry)
+                    && entry.timestamp().is_some_and(|date| {
+                        in_period(period, date.with_timezone(&Local).date_naive(), today)
+                    })
+            });
+            let (tokens, cost) = counted.fold((0u64, 0.0), |(tokens, cost), entry| {
+                (
+                    tokens + entry.total_tokens,
+                    cost + entry.cost.unwrap_or(0.0),
+                )
+            });
+            let status = |is_cost, used, cap| BudgetStatus {
+                period,
+                scope: scope.to_string(),
+                is_cost,
+                used,
+                cap,
+            };
+            if let Some(cap) = limit.tokens {
+                statuses.push(status(false, tokens as f64, cap as f64));
+            }
+            if let Some(cap) = limit.cost {
+                statuses.push(status(true, cost, cap));
+            }
+        }
+    }
+    statuses
+}
+
+/// The caps that apply to a chat (or, without one, to the current directory).
+pub fn statuses_for(chat_file: Option<&Path>, profile: Option<&str>) -> Result<Vec<BudgetStatus>> {
+    let start = chat_file.map_or_else(current_dir, Path::to_path_buf);
+    let config = load_config_for(&start)?;
+    if !has_budgets(&config) {
+        return Ok(Vec::new());
+    }
+    let entries = usage::load().map_err(|e| Error::io("Failed to read the usage ledger", e))?;
+    Ok(budget_statuses(
+        &config,
+        profile,
+        project_dir(&start).as_deref(),
+        &entries,
+    ))
+}
+
+/// Prints a "Budgets:" section, marking the caps that have been reached.
+pub fn print_budgets(statuses: &[BudgetStatus]) {
+    if statuses.is_empty() {
+        return;
+    }
+    println!("\nBudgets:");
+    for status in statuses {
+        let reached = if status.is_exceeded() {
+            " (reached)"
+        } else {
+            ""
+        };
+        println!("  {}{}", status.describe(), reached);
+    }
+}
+
+/// Warns when a cap is nearly used up, and refuses once one is reached unless `override_budget` is set.
+///
+/// Replayed requests cost nothing, so they are never refused.
+pub fn check_budget(
+    chat_file: Option<&Path>,
+    profile: Option<&str>,
+    override_budget: bool,
+) -> Result<()> {
+    if cassette::replaying() {
+        return Ok(());
+    }
+    let start = chat_file.map_or_else(current_dir, Path::to_path_buf);
+    let warn_at = load_config_for(&start)?.budgets.warn_at;
+    for status in statuses_for(chat_file, profile)? {
+        if status.is_exceeded() {
+            if !override_budget {
+                return Err(Error::Budget(format!(
+                    "Budget reached: {}. Use --override-budget to send the request anyway.",
+                    status.describe()
+                )));
+            }
+            eprintln!(
+                "Warning: {}; sending anyway because of --override-budget",
+                status.describe()
+            );
+        } else if status.fraction() >= warn_at {
+            eprintln!("Warning: {}", status.describe());
+        }
+    }
+    Ok(())
+}
+
+/// Whether `date` falls in the current `daily` or `monthly` period.
+fn in_period(period: &str, date: NaiveDate, today: NaiveDate) -> bool {
+    match period {
+        "daily" => date == today,
+        _ => (date.year(), date.month()) == (today.year(), today.month()),
+    }
+}
+
+fn has_budgets(config: &AppConfig) -> bool {
+    let budgets = &config.budgets;
+    budgets.daily.is_some()
+        || budgets.monthly.is_some()
+        || !budgets.profiles.is_empty()
+        || !budgets.projects.is_empty()
+}
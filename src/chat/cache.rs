@@ -0,0 +1,242 @@
+//! Opt-in on-disk cache of API answers, so identical requests aren't paid for twice.
+//!
+//! Answers are stored one per file in `~/.samvada/cache`, named by a hash of
+//! the endpoint and the request body. Requests with a temperature above 0
+//! may get a different answer each time, so they are only cached when forced.
+
+use chrono::{DateTime, Local};
+use clap::ArgMatches;
+use log::{debug, warn};
+use serde_json::{json, Value};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+
+use crate::chat::cassette;
+use crate::chat::config::{get_config_dir, parse_duration, CacheConfig};
+use crate::chat::logging::expand_home;
+use crate::chat::request_log::request_hash;
+
+/// Name of the cache directory in `~/.samvada`.
+const CACHE_DIR_NAME: &str = "cache";
+
+/// Field added to cached response bodies, holding when the answer was cached.
+pub const CACHED_AT_FIELD: &str = "x_samvada_cached_at";
+
+/// Whether to use the cache, as chosen with `--cache`, `--force-cache` or `--no-cache`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CacheMode {
+    /// As set in the `cache:` config.
+    Config,
+    On,
+    /// Also for requests with a temperature above 0.
+    Force,
+    Off,
+}
+
+impl CacheMode {
+    pub fn from_matches(matches: &ArgMatches) -> CacheMode {
+        if matches.get_flag("no-cache") {
+            CacheMode::Off
+        } else if matches.get_flag("force-cache") {
+            CacheMode::Force
+        } else if matches.get_flag("cache") {
+            CacheMode::On
+        } else {
+            CacheMode::Config
+        }
+    }
+}
+
+pub struct ResponseCache {
+    dir: PathBuf,
+    ttl: Duration,
+    max_size: u64,
+    force: bool,
+}
+
+static CACHE: OnceLock<Option<ResponseCache>> = OnceLock::new();
+
+/// Turns the cache on for this run if the config or the flags ask for it.
+pub fn init(config: &CacheConfig, mode: CacheMode) {
+    let enabled = match mode {
+        CacheMode::Config => config.enabled,
+        CacheMode::On | CacheMode::Force => true,
+        CacheMode::Off => false,
+    };
+    let cache = enabled.then(|| ResponseCache::open(config, mode == CacheMode::Force));
+    let _ = CACHE.set(cache.flatten());
+}
+
+/// The cache to use for a request, if it is on and the request may be cached.
+///
+/// Runs with a cassette always go to the cassette.
+pub fn for_request(body: &Value) -> Option<&'static ResponseCache> {
+    let cache = CACHE.get()?.as_ref()?;
+    if cassette::active().is_some() {
+        return None;
+    }
+    // The API's default temperature is 1, so a request without one varies too
+    let temperature = body["temperature"].as_f64().unwrap_or(1.0);
+    if temperature > 0.0 && !cache.force {
+        debug!(
+            "Not using the cache: temperature is {} (use --force-cache to cache anyway)",
+            temperature
+        );
+        return None;
+    }
+    Some(cache)
+}
+
+/// The key of a request: a hash of its endpoint, model, parameters and messages.
+pub fn cache_key(endpoint: &str, body: &Value) -> String {
+    request_hash(&json!({ "endpoint": endpoint, "body": body }))
+}
+
+impl ResponseCache {
+    /// Opens the cache directory configured in `cache:`, warning and returning `None` if the TTL is invalid.
+    pub fn open(config: &CacheConfig, force: bool) -> Option<ResponseCache> {
+        let Some(ttl) = parse_duration(&config.ttl) else {
+            warn!("Invalid cache ttl '{}'; not using the cache", config.ttl);
+            eprintln!(
+                "Warning: invalid cache ttl '{}'; use e.g. 12h or 7d. Not using the cache.",
+                config.ttl
+            );
+            return None;
+        };
+        let dir = match &config.dir {
+            Some(dir) => expand_home(dir),
+            None => get_config_dir().ok()?.join(CACHE_DIR_NAME),
+        };
+        Some(ResponseCache {
+            dir,
+            ttl,
+            max_size: config.max_size,
+            force: force || config.force,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the cached response body for `key`, marked with when it was cached.
+    pub fn get(&self, key: &str) -> Option<Value> {
+        let path = self.entry_path(key);
+        if self.is_expired(&path) {
+            return None;
+        }
+        let content = fs::read_to_string(&path).ok()?;
+        let entry: Value = serde_json::from_str(&content).ok()?;
+        let mut response = entry["response"].clone();
+        response[CACHED_AT_FIELD] = entry["cached_at"].clone();
+        Some(response)
+    }
+
+    /// Stores a response body, then removes expired answers and the oldest ones over the size limit.
+    pub fn put(&self, key: &str, endpoint: &str, response: &Value) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let entry = json!({
+            "cached_at": Local::now().to_rfc3339(),
+            "endpoint": endpoint,
+            "response": response,
+        });
+        fs::write(self.entry_path(key), entry.to_string())?;
+        self.prune()
+    }
+
+    /// The number of cached answers, how many have expired, and their total size in bytes.
+    pub fn stats(&self) -> io::Result<CacheStats> {
+        let mut stats = CacheStats::default();
+        for (path, size, modified) in self.entries()? {
+            stats.entries += 1;
+            stats.size += size;
+            if self.is_expired(&path) {
+                stats.expired += 1;
+            }
+            let modified = DateTime::<Local>::from(modified);
+            stats.oldest = Some(stats.oldest.map_or(modified, |oldest| oldest.min(modified)));
+            stats.newest = Some(stats.newest.map_or(modified, |newest| newest.max(modified)));
+        }
+        Ok(stats)
+    }
+
+    /// Removes every cached answer, or only the expired ones, returning how many were removed.
+    pub fn clear(&self, expired_only: bool) -> io::Result<usize> {
+        let mut removed = 0;
+        for (path, _, _) in self.entries()? {
+            if !expired_only || self.is_expired(&path) {
+                fs::remove_file(&path)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    fn prune(&self) -> io::Result<()> {
+        let mut entries = Vec::new();
+        for entry in self.entries()? {
+            if self.is_expired(&entry.0) {
+                fs::remove_file(&entry.0)?;
+            } else {
+                entries.push(entry);
+            }
+        }
+        // Oldest first
+        entries.sort_by_key(|(_, _, modified)| *modified);
+        let mut size: u64 = entries.iter().map(|(_, size, _)| size).sum();
+        for (path, entry_size, _) in entries {
+            if size <= self.max_size {
+                break;
+            }
+            fs::remove_file(&path)?;
+            size -= entry_size;
+        }
+        Ok(())
+    }
+
+    /// The cached answers' paths, sizes and modification times.
+    fn entries(&self) -> io::Result<Vec<(PathBuf, u64, SystemTime)>> {
+        let read_dir = match fs::read_dir(&self.dir) {
+            Ok(read_dir) => read_dir,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut entries = Vec::new();
+        for entry in read_dir {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                let metadata = fs::metadata(&path)?;
+                entries.push((path, metadata.len(), metadata.modified()?));
+            }
+        }
+        Ok(entries)
+    }
+
+    fn is_expired(&self, path: &Path) -> bool {
+        let age = fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok());
+        age.is_none_or(|age| age > self.ttl)
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+}
+
+/// Counts reported by `samvada cache stats`.
+#[derive(Debug, Default)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub expired: usize,
+    pub size: u64,
+    pub oldest: Option<DateTime<Local>>,
+    pub newest: Option<DateTime<Local>>,
+}
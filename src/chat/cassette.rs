@@ -0,0 +1,151 @@
+//! Record and replay API responses, for offline and deterministic runs.
+//!
+//! A cassette is a JSON file mapping the hash of each request's endpoint and
+//! body to the response it got. `--record` sends requests as usual and saves what came
+//! back; `--replay` answers from the cassette without touching the network.
+//! Request headers, and with them the `Authorization` header, are never saved,
+//! and responses are stored as received so replays append the same text.
+
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::chat::cache::cache_key;
+use crate::chat::credentials::write_private_file;
+use crate::chat::request_log::redact_key;
+use crate::error::{Error, Result};
+
+/// Environment variable that records to a cassette, like `--record`.
+pub const RECORD_VAR: &str = "SAMVADA_RECORD";
+/// Environment variable that replays a cassette, like `--replay`.
+pub const REPLAY_VAR: &str = "SAMVADA_REPLAY";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Record,
+    Replay,
+}
+
+#[derive(Debug)]
+pub struct Cassette {
+    pub mode: Mode,
+    pub path: PathBuf,
+}
+
+static CASSETTE: OnceLock<Option<Cassette>> = OnceLock::new();
+
+/// Chooses the cassette from `--record`/`--replay`, else from `SAMVADA_RECORD`/`SAMVADA_REPLAY`.
+pub fn init(record: Option<&str>, replay: Option<&str>) -> Result<()> {
+    let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+    let cassette = match (record, replay) {
+        (Some(path), _) => Some((Mode::Record, path.to_string())),
+        (None, Some(path)) => Some((Mode::Replay, path.to_string())),
+        (None, None) => match (var(RECORD_VAR), var(REPLAY_VAR)) {
+            (Some(_), Some(_)) => {
+                return Err(Error::Usage(format!(
+                    "Only one of {} and {} can be set.",
+                    RECORD_VAR, REPLAY_VAR
+                )))
+            }
+            (Some(path), None) => Some((Mode::Record, path)),
+            (None, Some(path)) => Some((Mode::Replay, path)),
+            (None, None) => None,
+        },
+    };
+    let _ = CASSETTE.set(cassette.map(|(mode, path)| Cassette {
+        mode,
+        path: PathBuf::from(path),
+    }));
+    Ok(())
+}
+
+/// The cassette in use, if any.
+pub fn active() -> Option<&'static Cassette> {
+    CASSETTE.get().and_then(Option::as_ref)
+}
+
+/// Whether requests are answered from a cassette instead of the API.
+pub fn replaying() -> bool {
+    active().is_some_and(|cassette| cassette.mode == Mode::Replay)
+}
+
+impl Cassette {
+    /// Returns the recorded status and body of the response to `body` sent to `endpoint`.
+    pub fn replay(&self, endpoint: &str, body: &Value) -> Result<(u16, String)> {
+        let hash = cache_key(endpoint, body);
+        let interactions = load(&self.path)?;
+        let Some(interaction) = interactions.get(&hash) else {
+            return Err(Error::CassetteMiss(format!(
+                "No recorded response for request {} in cassette {}; record it again with --record",
+                hash,
+                self.path.display()
+            )));
+        };
+        let status = interaction["status"].as_u64().unwrap_or(200) as u16;
+        let response = match &interaction["response"] {
+            Value::String(text) => text.clone(),
+            response => response.to_string(),
+        };
+        Ok((status, response))
+    }
+
+    /// Saves the response to `body`, replacing an earlier recording of the same request.
+    pub fn record(
+        &self,
+        api_key: &str,
+        endpoint: &str,
+        body: &Value,
+        status: u16,
+        response: &str,
+    ) -> Result<()> {
+        let failed = |e| Error::io(format!("Failed to record to {}", self.path.display()), e);
+        let mut interactions = load(&self.path)?;
+        let response: Value =
+            serde_json::from_str(response).unwrap_or_else(|_| Value::String(response.to_string()));
+        let interaction = json!({
+            "endpoint": endpoint,
+            "request": body,
+            "status": status,
+            "response": response,
+        });
+        // Bodies don't carry the key, but an endpoint URL or an error message might
+        let interaction = serde_json::from_str(&redact_key(&interaction.to_string(), api_key))
+            .map_err(|e| failed(io::Error::other(e)))?;
+        interactions.insert(cache_key(endpoint, body), interaction);
+
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(failed)?;
+        }
+        let content = serde_json::to_string_pretty(&json!({ "interactions": interactions }))
+            .map_err(|e| failed(io::Error::other(e)))?;
+        // Replaced in one step, so an interrupted run keeps the earlier recordings
+        write_private_file(&self.path, (content + "\n").as_bytes()).map_err(failed)
+    }
+}
+
+/// Reads the interactions of a cassette, keyed by request hash; a missing file has none.
+fn load(path: &Path) -> Result<Map<String, Value>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Map::new()),
+        Err(e) => {
+            return Err(Error::io(
+                format!("Failed to read cassette {}", path.display()),
+                e,
+            ))
+        }
+    };
+    let cassette: Value = serde_json::from_str(&content).map_err(|e| {
+        Error::Parse(format!(
+            "Cassette {} is not valid JSON: {}",
+            path.display(),
+            e
+        ))
+    })?;
+    Ok(cassette["interactions"]
+        .as_object()
+        .cloned()
+        .unwrap_or_default())
+}
@@ -1,16 +1,75 @@
-use config::Config;
+use config::builder::DefaultState;
+use config::{Config, ConfigBuilder};
 use dirs::home_dir;
 use log::debug;
-use serde::Deserialize;
-use std::fs::{self, File, OpenOptions};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fs::{self, File};
 use std::io::{Error as IoError, Read, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
 
 use crate::chat::constants::ADD_OPENAI_KEY_MESSAGE;
+use crate::chat::credentials::{
+    credentials_path, endpoint_host, write_private_file, Credential, Credentials,
+};
+use crate::chat::lint::Severity;
+use crate::chat::parser::ParsedChat;
+use crate::chat::personas::Persona;
+use crate::chat::vault::{vault_path, Vault};
+use crate::error::Error;
 
 // Replace the const string with include_str!
 const DEFAULT_CONFIG: &str = include_str!("../config.yml");
 
+/// Name of the global configuration file in `~/.samvada`.
+pub const CONFIG_FILE_NAME: &str = "config.yaml";
+
+/// Name the global configuration file had in older releases.
+pub const LEGACY_CONFIG_FILE_NAME: &str = "config.yml";
+
+/// Name of the per-project configuration file, found by walking up from a path.
+pub const PROJECT_CONFIG_FILE_NAME: &str = ".samvada.yaml";
+
+/// Environment variables that override top-level config keys, as `(variable, key)`.
+pub const ENV_OVERRIDES: &[(&str, &str)] = &[
+    ("SAMVADA_SYSTEM_PROMPT", "system_prompt"),
+    ("SAMVADA_MODEL", "model"),
+    ("SAMVADA_API_ENDPOINT", "api_endpoint"),
+    ("SAMVADA_PROFILE", "profile"),
+];
+
+/// Sections keyed by names, paths or request parameters, as dotted paths.
+///
+/// The `config` crate lowercases every key it reads, so `load_config_for`
+/// reads these sections again exactly as written.
+const CASE_SENSITIVE_SECTIONS: &[&str] = &[
+    "personas",
+    "profiles",
+    "budgets.profiles",
+    "budgets.projects",
+    "pricing",
+];
+
+/// Variable holding the API key when no profile names another one.
+pub const DEFAULT_API_KEY_VAR: &str = "OPENAI_API_KEY";
+
+/// Profile chosen with the global `--profile` flag.
+static PROFILE_FLAG: OnceLock<String> = OnceLock::new();
+
+/// Records the profile chosen with the global `--profile` flag.
+pub fn set_profile_flag(name: &str) {
+    let _ = PROFILE_FLAG.set(name.to_string());
+}
+
+/// Returns the profile chosen with the global `--profile` flag, if any.
+pub fn profile_flag() -> Option<&'static str> {
+    PROFILE_FLAG.get().map(String::as_str)
+}
+
 /// Returns the directory path where configuration files are stored.
 pub fn get_config_dir() -> std::io::Result<PathBuf> {
     let home = home_dir().ok_or_else(|| {
@@ -34,32 +93,457 @@ pub fn get_env_file_path() -> std::io::Result<PathBuf> {
     Ok(get_config_dir()?.join(".env"))
 }
 
-/// Saves the API key to the environment configuration file.
-pub fn save_api_key(api_key: &str) -> std::io::Result<()> {
-    let env_path = get_env_file_path()?;
-    let mut file = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(env_path)?;
-
-    writeln!(file, "OPENAI_API_KEY={}", api_key)?;
-    println!("API key saved successfully in ~/.samvada/.env!");
+/// Saves the API key as the key for `host`, in the vault if there is one,
+/// otherwise in the credential store.
+pub fn save_api_key(api_key: &str, host: &str) -> Result<(), Error> {
+    let credential = Credential {
+        host: host.to_string(),
+        api_key: api_key.to_string(),
+    };
+    let vault =
+        Vault::open(true).map_err(|e| Error::Auth(format!("Failed to open the vault: {}", e)))?;
+    let saved = match vault {
+        Some(mut vault) => {
+            vault.credentials.keys.insert(host.to_string(), credential);
+            vault.save()
+        }
+        None => Credentials::load().and_then(|mut credentials| {
+            credentials.keys.insert(host.to_string(), credential);
+            credentials.save()
+        }),
+    };
+    let path = saved.map_err(|e| Error::io("Failed to save API key", e))?;
+    println!(
+        "API key for {} saved successfully in {}!",
+        host,
+        path.display()
+    );
     Ok(())
 }
 
+/// Sets `variable=value` in the environment configuration file, keeping its other lines.
+pub fn save_env_var(variable: &str, value: &str) -> std::io::Result<PathBuf> {
+    let env_path = get_env_file_path()?;
+    rewrite_env_file(&env_path, variable, Some(value))?;
+    Ok(env_path)
+}
+
+/// Removes `variable` from the .env file at `env_path`. Returns whether it was set.
+pub fn remove_env_var(env_path: &Path, variable: &str) -> std::io::Result<bool> {
+    rewrite_env_file(env_path, variable, None)
+}
+
+/// Replaces the first assignment of `variable` with `value` (appending it if
+/// absent) or removes it when `value` is `None`; later duplicates are dropped.
+///
+/// The file is replaced atomically and readable only by the current user.
+fn rewrite_env_file(env_path: &Path, variable: &str, value: Option<&str>) -> std::io::Result<bool> {
+    let existing = match fs::read_to_string(env_path) {
+        Ok(existing) => existing,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    let assignment = value.map(|value| format!("{}={}", variable, value));
+
+    let mut found = false;
+    let mut lines: Vec<String> = existing
+        .lines()
+        .filter_map(|line| {
+            let is_variable = parse_env_line(line).is_some_and(|(name, _)| name == variable);
+            match (is_variable, found) {
+                (false, _) => Some(line.to_string()),
+                (true, false) => {
+                    found = true;
+                    assignment.clone()
+                }
+                // Drop later duplicates so the new value is the only one
+                (true, true) => None,
+            }
+        })
+        .collect();
+    if !found {
+        match assignment {
+            Some(assignment) => lines.push(assignment),
+            None => return Ok(false),
+        }
+    }
+
+    let mut content = lines.join("\n");
+    if !content.is_empty() {
+        content.push('\n');
+    }
+    write_private_file(env_path, content.as_bytes())?;
+    Ok(found)
+}
+
+/// Splits a .env line into its variable name and value, or `None` for comments
+/// and lines without an assignment.
+///
+/// Like dotenv, an `export ` prefix is allowed and quotes around the value are removed.
+pub fn parse_env_line(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim();
+    if line.starts_with('#') {
+        return None;
+    }
+    let line = line.strip_prefix("export ").unwrap_or(line);
+    let (name, value) = line.split_once('=')?;
+    let value = value.trim();
+    let unquoted = ['"', '\'']
+        .into_iter()
+        .find_map(|quote| value.strip_prefix(quote)?.strip_suffix(quote));
+    Some((name.trim(), unquoted.unwrap_or(value)))
+}
+
+/// Returns the non-empty value of the first assignment of `variable` in .env file content.
+pub fn env_file_value(content: &str, variable: &str) -> Option<String> {
+    content
+        .lines()
+        .filter_map(parse_env_line)
+        .find(|(name, _)| *name == variable)
+        .map(|(_, value)| value.to_string())
+        .filter(|value| !value.is_empty())
+}
+
 // Add a struct to hold the configurations
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct AppConfig {
     pub system_prompt: String,
     pub model: String,
     pub api_endpoint: String,
+    #[serde(default)]
+    pub lint: LintConfig,
+    /// Named presets selected with `persona:` in a chat's frontmatter.
+    #[serde(default)]
+    pub personas: HashMap<String, Persona>,
+    /// Profile used when neither `--profile` nor the frontmatter selects one.
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Named endpoint, model and key setups, such as `work` and `personal`.
+    #[serde(default)]
+    pub profiles: HashMap<String, Profile>,
+    #[serde(default)]
+    pub request_log: RequestLogConfig,
+    /// Whether to write log files at all; `logging: off` turns them off.
+    #[serde(default = "default_logging")]
+    pub logging: bool,
+    /// Directory for log files, instead of next to each chat.
+    #[serde(default)]
+    pub log_dir: Option<String>,
+    /// Also print log messages to stderr.
+    #[serde(default)]
+    pub log_stderr: bool,
+    /// Prices by model, over the built-in ones; a trailing `*` matches by prefix.
+    #[serde(default)]
+    pub pricing: HashMap<String, Price>,
+    #[serde(default)]
+    pub budgets: BudgetConfig,
+    #[serde(default)]
+    pub cache: CacheConfig,
+}
+
+fn default_logging() -> bool {
+    true
+}
+
+impl AppConfig {
+    /// Looks up the price of a model in `pricing:`, then in the built-in prices.
+    pub fn price_for(&self, model: &str) -> Option<Price> {
+        let builtin = builtin_pricing();
+        find_price(&self.pricing, model)
+            .or_else(|| find_price(&builtin, model))
+            .cloned()
+    }
+
+    /// Looks up a profile by name.
+    pub fn find_profile(&self, name: &str) -> Result<&Profile, String> {
+        self.profiles.get(name).ok_or_else(|| {
+            format!(
+                "unknown profile '{}'. Define it under `profiles:` in config.yaml",
+                name
+            )
+        })
+    }
+}
+
+/// An account setup under `profiles:`: where to send requests and with which key.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct Profile {
+    #[serde(default)]
+    pub api_endpoint: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Name of the variable, in a `.env` file or the environment, that holds the API key.
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+    /// Name of the key saved with `samvada auth login --name` to use.
+    #[serde(default)]
+    pub credential: Option<String>,
+    #[serde(default)]
+    pub params: Map<String, Value>,
+}
+
+impl Profile {
+    /// Sets the profile's endpoint, model, params and key variable on a chat.
+    pub fn apply(&self, chat: &mut ParsedChat) {
+        if let Some(api_endpoint) = &self.api_endpoint {
+            chat.api_endpoint = api_endpoint.clone();
+        }
+        if let Some(model) = &self.model {
+            chat.model = model.clone();
+        }
+        chat.params.extend(self.params.clone());
+        if let Some(api_key_env) = &self.api_key_env {
+            chat.api_key_env = Some(api_key_env.clone());
+        }
+        if let Some(credential) = &self.credential {
+            chat.credential = Some(credential.clone());
+        }
+    }
+}
+
+/// Price of a model in US dollars per million tokens, under `pricing:`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Price {
+    pub prompt: f64,
+    pub completion: f64,
+}
+
+impl Price {
+    /// Estimated cost in US dollars of a request with these token counts.
+    pub fn cost(&self, prompt_tokens: u64, completion_tokens: u64) -> f64 {
+        (prompt_tokens as f64 * self.prompt + completion_tokens as f64 * self.completion)
+            / 1_000_000.0
+    }
+}
+
+/// Finds an exact match first, else the longest matching `*` prefix.
+fn find_price<'a>(pricing: &'a HashMap<String, Price>, model: &str) -> Option<&'a Price> {
+    pricing.get(model).or_else(|| {
+        pricing
+            .iter()
+            .filter_map(|(key, price)| Some((key.strip_suffix('*')?, price)))
+            .filter(|(prefix, _)| model.starts_with(prefix))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, price)| price)
+    })
+}
+
+/// Published OpenAI prices, used for models missing from `pricing:`.
+fn builtin_pricing() -> HashMap<String, Price> {
+    [
+        ("gpt-4o*", 2.5, 10.0),
+        ("gpt-4o-mini*", 0.15, 0.6),
+        ("gpt-4.1*", 2.0, 8.0),
+        ("gpt-4.1-mini*", 0.4, 1.6),
+        ("gpt-4.1-nano*", 0.1, 0.4),
+        ("gpt-3.5-turbo*", 0.5, 1.5),
+        ("o3*", 2.0, 8.0),
+        ("o4-mini*", 1.1, 4.4),
+    ]
+    .into_iter()
+    .map(|(model, prompt, completion)| (model.to_string(), Price { prompt, completion }))
+    .collect()
+}
+
+/// Spending caps, under the `budgets:` key.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BudgetConfig {
+    #[serde(default)]
+    pub daily: Option<Limit>,
+    #[serde(default)]
+    pub monthly: Option<Limit>,
+    /// Fraction of a cap at which to start warning.
+    #[serde(default = "default_warn_at")]
+    pub warn_at: f64,
+    /// Caps for requests made with a profile, by profile name.
+    #[serde(default)]
+    pub profiles: HashMap<String, Budget>,
+    /// Caps for requests from a project, by project directory.
+    #[serde(default)]
+    pub projects: HashMap<String, Budget>,
+}
+
+impl Default for BudgetConfig {
+    fn default() -> Self {
+        BudgetConfig {
+            daily: None,
+            monthly: None,
+            warn_at: default_warn_at(),
+            profiles: HashMap::new(),
+            projects: HashMap::new(),
+        }
+    }
+}
+
+fn default_warn_at() -> f64 {
+    0.8
+}
+
+/// Daily and monthly caps for one profile or project.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct Budget {
+    #[serde(default)]
+    pub daily: Option<Limit>,
+    #[serde(default)]
+    pub monthly: Option<Limit>,
+}
+
+/// A cap in tokens, in US dollars, or both.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct Limit {
+    #[serde(default)]
+    pub tokens: Option<u64>,
+    #[serde(default)]
+    pub cost: Option<f64>,
+}
+
+/// Settings for the JSON-lines request log, under the `request_log:` key.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RequestLogConfig {
+    /// Also record the full request and response bodies.
+    #[serde(default)]
+    pub payloads: bool,
+    /// Size in bytes after which the log is rotated.
+    #[serde(default = "default_request_log_max_size")]
+    pub max_size: u64,
+    /// How many rotated logs to keep.
+    #[serde(default = "default_request_log_keep")]
+    pub keep: usize,
+}
+
+impl Default for RequestLogConfig {
+    fn default() -> Self {
+        RequestLogConfig {
+            payloads: false,
+            max_size: default_request_log_max_size(),
+            keep: default_request_log_keep(),
+        }
+    }
+}
+
+fn default_request_log_max_size() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_request_log_keep() -> usize {
+    3
 }
 
-/// Ensures the config file exists, creating it with defaults if it doesn't
+/// Settings for the response cache, under the `cache:` key.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CacheConfig {
+    /// Answer repeated requests from the cache; `--cache` turns it on for one run.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Also cache requests whose temperature is above 0.
+    #[serde(default)]
+    pub force: bool,
+    /// How long answers are kept, e.g. `12h` or `7d`.
+    #[serde(default = "default_cache_ttl")]
+    pub ttl: String,
+    /// Size in bytes above which the oldest answers are removed.
+    #[serde(default = "default_cache_max_size")]
+    pub max_size: u64,
+    /// Directory for cached answers, instead of `~/.samvada/cache`.
+    #[serde(default)]
+    pub dir: Option<String>,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            enabled: false,
+            force: false,
+            ttl: default_cache_ttl(),
+            max_size: default_cache_max_size(),
+            dir: None,
+        }
+    }
+}
+
+fn default_cache_ttl() -> String {
+    "7d".to_string()
+}
+
+fn default_cache_max_size() -> u64 {
+    50 * 1024 * 1024
+}
+
+/// Parses durations such as `90`, `90s`, `15m`, `2h` and `7d`; plain numbers are seconds.
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => value.split_at(index),
+        None => (value, "s"),
+    };
+    let number: u64 = number.parse().ok()?;
+    let seconds = match unit {
+        "s" => number,
+        "m" => number * 60,
+        "h" => number * 60 * 60,
+        "d" => number * 60 * 60 * 24,
+        _ => return None,
+    };
+    (seconds > 0).then(|| Duration::from_secs(seconds))
+}
+
+/// Settings for `chat lint`, under the `lint:` key.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LintConfig {
+    /// Severity overrides by rule id: `error`, `warning` or `off`.
+    #[serde(default)]
+    pub rules: HashMap<String, Severity>,
+    /// Frontmatter keys that may be left without a value.
+    #[serde(default = "default_allow_empty")]
+    pub allow_empty: Vec<String>,
+    /// Models the `unknown-model` rule accepts; a trailing `*` matches by prefix.
+    #[serde(default = "default_known_models")]
+    pub known_models: Vec<String>,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        LintConfig {
+            rules: HashMap::new(),
+            allow_empty: default_allow_empty(),
+            known_models: default_known_models(),
+        }
+    }
+}
+
+fn default_allow_empty() -> Vec<String> {
+    vec!["summary".to_string()]
+}
+
+fn default_known_models() -> Vec<String> {
+    [
+        "gpt-4o*",
+        "gpt-4.1*",
+        "gpt-4-turbo*",
+        "gpt-4",
+        "gpt-3.5-turbo*",
+        "chatgpt-4o-latest",
+        "o1*",
+        "o3*",
+        "o4-mini*",
+    ]
+    .into_iter()
+    .map(str::to_string)
+    .collect()
+}
+
+/// Ensures the config file exists, creating it with defaults if it doesn't.
+///
+/// A legacy `config.yml` is renamed to `config.yaml` when that doesn't exist yet.
 pub fn ensure_config_exists() -> std::io::Result<PathBuf> {
     let config_dir = get_config_dir()?;
-    let config_path = config_dir.join("config.yaml");
+    let config_path = config_dir.join(CONFIG_FILE_NAME);
+    let legacy_path = config_dir.join(LEGACY_CONFIG_FILE_NAME);
+
+    if !config_path.exists() && legacy_path.is_file() {
+        fs::rename(&legacy_path, &config_path)?;
+        eprintln!("Moved ~/.samvada/config.yml to ~/.samvada/config.yaml");
+    }
 
     if !config_path.exists() {
         debug!("Creating default config file at: {}", config_path.display());
@@ -71,61 +555,261 @@ pub fn ensure_config_exists() -> std::io::Result<PathBuf> {
     Ok(config_path)
 }
 
-/// Loads the application configuration from the YAML file.
+/// Loads the configuration that applies in the current directory.
 pub fn load_config() -> Result<AppConfig, config::ConfigError> {
+    let current_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    load_config_for(&current_dir)
+}
+
+/// Loads the configuration that applies to `start`, a chat file or directory.
+///
+/// Layers, lowest first: the global `~/.samvada/config.yaml`, the nearest
+/// project `.samvada.yaml` above `start`, then the `SAMVADA_*` variables in
+/// `ENV_OVERRIDES`. A chat's frontmatter is applied on top by the parser.
+pub fn load_config_for(start: &Path) -> Result<AppConfig, config::ConfigError> {
+    let project_config = find_project_config(start);
+    let mut config = config_builder(project_config.as_deref())?
+        .build()?
+        .try_deserialize()?;
+    restore_key_case(&mut config, project_config.as_deref())?;
+    Ok(config)
+}
+
+/// Replaces the `CASE_SENSITIVE_SECTIONS` of a loaded config with the sections
+/// as written in its files, merged the same way.
+fn restore_key_case(
+    config: &mut AppConfig,
+    project_config: Option<&Path>,
+) -> Result<(), config::ConfigError> {
+    let global =
+        ensure_config_exists().map_err(|e| config::ConfigError::NotFound(e.to_string()))?;
+    let mut written = serde_yaml::Value::Null;
+    for path in std::iter::once(global.as_path()).chain(project_config) {
+        let layer = read_yaml_file(path)
+            .map_err(|e| config::ConfigError::Message(format!("{}: {}", path.display(), e)))?;
+        merge_yaml(&mut written, layer);
+    }
+
+    let mut loaded =
+        serde_json::to_value(&*config).map_err(|e| config::ConfigError::Message(e.to_string()))?;
+    for section in CASE_SENSITIVE_SECTIONS {
+        let Some(value) = section
+            .split('.')
+            .try_fold(&written, |value, key| value.get(key))
+            .filter(|value| !value.is_null())
+        else {
+            continue;
+        };
+        if let Some(slot) = loaded.pointer_mut(&format!("/{}", section.replace('.', "/"))) {
+            *slot = serde_json::to_value(value)
+                .map_err(|e| config::ConfigError::Message(format!("{}: {}", section, e)))?;
+        }
+    }
+    *config =
+        serde_json::from_value(loaded).map_err(|e| config::ConfigError::Message(e.to_string()))?;
+    Ok(())
+}
+
+/// Merges `layer` into `base`: mappings key by key, anything else replaced.
+fn merge_yaml(base: &mut serde_yaml::Value, layer: serde_yaml::Value) {
+    match (base, layer) {
+        (serde_yaml::Value::Mapping(base), serde_yaml::Value::Mapping(layer)) => {
+            for (key, value) in layer {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_yaml(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, layer) => *base = layer,
+    }
+}
+
+/// Reads a YAML file as written, keeping the case of its keys; an empty file is an empty mapping.
+pub fn read_yaml_file<T: DeserializeOwned>(path: &Path) -> Result<T, String> {
+    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let content = if content.trim().is_empty() {
+        "{}"
+    } else {
+        &content
+    };
+    serde_yaml::from_str(content).map_err(|e| e.to_string())
+}
+
+/// Walks up from `start` (a file or directory) looking for a project config file.
+pub fn find_project_config(start: &Path) -> Option<PathBuf> {
+    let start = start.canonicalize().ok()?;
+    let start = if start.is_file() {
+        start.parent()?.to_path_buf()
+    } else {
+        start
+    };
+
+    start
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG_FILE_NAME))
+        .find(|candidate| candidate.is_file())
+}
+
+fn config_builder(
+    project_config: Option<&Path>,
+) -> Result<ConfigBuilder<DefaultState>, config::ConfigError> {
     let config_path =
         ensure_config_exists().map_err(|e| config::ConfigError::NotFound(e.to_string()))?;
 
-    let config = Config::builder()
-        .add_source(config::File::with_name(config_path.to_str().unwrap()))
-        .build()?;
+    let mut builder = Config::builder().add_source(config::File::from(config_path));
+    if let Some(project_config) = project_config {
+        debug!("Using project config file: {}", project_config.display());
+        builder = builder.add_source(config::File::from(project_config));
+    }
+    for (variable, key, value) in env_overrides() {
+        debug!("Using {} from {}", key, variable);
+        builder = builder.set_override(key, value)?;
+    }
+
+    Ok(builder)
+}
 
-    config.try_deserialize()
+/// Returns the `ENV_OVERRIDES` that are set, as `(variable, key, value)`.
+pub fn env_overrides() -> Vec<(&'static str, &'static str, String)> {
+    ENV_OVERRIDES
+        .iter()
+        .filter_map(|(variable, key)| {
+            let value = std::env::var(variable)
+                .ok()
+                .filter(|value| !value.is_empty())?;
+            Some((*variable, *key, value))
+        })
+        .collect()
 }
 
-/// Gets the API key with explicit precedence:
+/// Describes which layer sets `key` (a dotted path such as `lint.rules.empty-chat`)
+/// for a configuration loaded with `project_config`.
+pub fn config_origin(key: &str, project_config: Option<&Path>) -> String {
+    if let Some((variable, _, _)) = env_overrides()
+        .into_iter()
+        .find(|(_, overridden, _)| *overridden == key)
+    {
+        return format!("env {}", variable);
+    }
+
+    let defines = |path: &Path| {
+        read_yaml_file::<serde_yaml::Value>(path).is_ok_and(|layer| {
+            key.split('.')
+                .try_fold(&layer, |value, key| value.get(key))
+                .is_some()
+        })
+    };
+    if let Some(project_config) = project_config.filter(|path| defines(path)) {
+        return format!("project {}", project_config.display());
+    }
+    match ensure_config_exists() {
+        Ok(global) if defines(&global) => format!("global {}", global.display()),
+        _ => "default".to_string(),
+    }
+}
+
+/// An API key and a description of where it was found.
+pub struct ApiKey {
+    pub key: String,
+    pub origin: String,
+}
+
+/// Finds the API key for a chat with explicit precedence:
 /// 1. Command line argument
-/// 2. .env file in config directory
-/// 3. Environment variable
-pub fn get_api_key(cli_key: Option<&String>) -> String {
+/// 2. The encrypted vault, if one was created (asking for the passphrase
+///    on a terminal unless `samvada auth vault unlock` cached it)
+/// 3. The credential store
+/// 4. .env file (current directory, else config directory)
+/// 5. Environment variable
+///
+/// Steps 2 and 3 use the key the profile names, else the one for the endpoint host.
+/// Steps 4 and 5 read the profile's `api_key_env` variable, `OPENAI_API_KEY` if unset.
+pub fn resolve_api_key(cli_key: Option<&String>, chat: &ParsedChat) -> Option<ApiKey> {
+    let key_var = chat.api_key_env.as_deref().unwrap_or(DEFAULT_API_KEY_VAR);
+
     cli_key
         .map(|key| {
             debug!("Using API key from command line arguments");
-            key.to_string()
+            ApiKey {
+                key: key.to_string(),
+                origin: "--api-key".to_string(),
+            }
         })
         .or_else(|| {
-            get_env_file_path()
-                .ok()
-                .and_then(|env_path| {
-                    let absolute_path =
-                        env_path.canonicalize().unwrap_or_else(|_| env_path.clone());
-                    debug!(
-                        "Loading environment from absolute path: {}",
-                        absolute_path.display()
-                    );
-
-                    // Read the .env file directly
-                    let mut file = File::open(&env_path).ok()?;
-                    let mut contents = String::new();
-                    file.read_to_string(&mut contents).ok()?;
-
-                    // Parse each line looking for OPENAI_API_KEY
-                    contents
-                        .lines()
-                        .find(|line| line.starts_with("OPENAI_API_KEY="))
-                        .map(|line| line.splitn(2, '=').nth(1).unwrap_or("").trim().to_string())
-                        .filter(|key| !key.is_empty())
-                })
-                .map(|key| {
-                    debug!("Using API key from .env file");
-                    key
-                })
+            let vault = match Vault::open(true) {
+                Ok(vault) => vault?,
+                Err(e) => {
+                    eprintln!("Skipping the vault: {}", e);
+                    return None;
+                }
+            };
+            let host = endpoint_host(&chat.api_endpoint);
+            let (name, credential) = vault.credentials.find(chat.credential.as_deref(), &host)?;
+            debug!("Using API key '{}' from the vault", name);
+            Some(ApiKey {
+                key: credential.api_key.clone(),
+                origin: format!("vault key '{}' in {}", name, vault_path().ok()?.display()),
+            })
         })
         .or_else(|| {
-            std::env::var("OPENAI_API_KEY").ok().map(|key| {
-                debug!("Using API key from terminal environment variables");
-                key
+            let credentials = Credentials::load()
+                .inspect_err(|e| debug!("Ignoring credential store: {}", e))
+                .ok()?;
+            let host = endpoint_host(&chat.api_endpoint);
+            let (name, credential) = credentials.find(chat.credential.as_deref(), &host)?;
+            debug!("Using API key '{}' from the credential store", name);
+            Some(ApiKey {
+                key: credential.api_key.clone(),
+                origin: format!(
+                    "credential '{}' in {}",
+                    name,
+                    credentials_path().ok()?.display()
+                ),
             })
         })
-        .expect(ADD_OPENAI_KEY_MESSAGE)
+        .or_else(|| {
+            let env_path = get_env_file_path().ok()?;
+            let absolute_path = env_path.canonicalize().unwrap_or_else(|_| env_path.clone());
+            debug!(
+                "Loading environment from absolute path: {}",
+                absolute_path.display()
+            );
+
+            // Read the .env file directly
+            let mut file = File::open(&env_path).ok()?;
+            let mut contents = String::new();
+            file.read_to_string(&mut contents).ok()?;
+
+            let key = env_file_value(&contents, key_var)?;
+            debug!("Using API key from .env file");
+            Some(ApiKey {
+                key,
+                origin: format!("{} in {}", key_var, absolute_path.display()),
+            })
+        })
+        .or_else(|| {
+            let key = std::env::var(key_var).ok().filter(|key| !key.is_empty())?;
+            debug!("Using API key from terminal environment variables");
+            Some(ApiKey {
+                key,
+                origin: format!("environment variable {}", key_var),
+            })
+        })
+}
+
+/// Gets the API key for a chat, see `resolve_api_key` for the precedence.
+pub fn get_api_key(cli_key: Option<&String>, chat: &ParsedChat) -> Result<String, Error> {
+    match resolve_api_key(cli_key, chat) {
+        Some(api_key) => Ok(api_key.key),
+        None => Err(Error::Auth(match &chat.api_key_env {
+            Some(key_var) => format!(
+                "API key not found! The active profile reads it from {}; set it in your .env file or environment, or run `samvada auth login`.",
+                key_var
+            ),
+            None => ADD_OPENAI_KEY_MESSAGE.to_string(),
+        })),
+    }
 }
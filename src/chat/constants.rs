@@ -9,8 +9,27 @@ tags: {tags}
 summary: {summary}
 ---";
 
+/// Frontmatter keys that samvada understands but `chat create` doesn't always write.
+pub const OPTIONAL_FRONTMATTER_KEYS: &[&str] = &["params", "persona", "profile", "private"];
+
+/// Keys written as `<!-- key: value -->` comments after each assistant answer.
+pub const METADATA_KEYS: &[&str] = &[
+    "model",
+    "id",
+    "created",
+    "prompt_tokens",
+    "completion_tokens",
+    "total_tokens",
+    "cached_tokens",
+    "reasoning_tokens",
+    "finish_reason",
+    "latency_ms",
+    "cost",
+    "from_cache",
+];
+
 pub const ADD_OPENAI_KEY_MESSAGE: &str = "OpenAI API key not found! Please set it using one of these methods:\n\
-1. Run the command with your API key using --api-key=your-api-key-here\n\
+1. Run `samvada auth login`, or the command with your API key using --api-key=your-api-key-here\n\
 2. Set it in your .env file\n\
 3. Set it as an environment variable:\n\
    - Windows (Command Prompt): set OPENAI_API_KEY=your-api-key-here\n\
@@ -1,45 +1,168 @@
 use crate::chat::constants::FRONTMATTER_TEMPLATE;
 use chrono::Utc;
 use clap::{Arg, ArgMatches, Command};
-use std::fs::File;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
 use std::io::{self, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use crate::chat::config::load_config;
+use crate::chat::config::{load_config_for, profile_flag};
+use crate::chat::personas::find_persona;
+use crate::chat::templates::render_template;
+use crate::error::{Error, Result};
 
-pub fn handle_create_subcommand(matches: &ArgMatches) {
+/// Values that take precedence over the config defaults in a new chat file.
+#[derive(Debug, Default, Clone)]
+pub struct ChatOptions {
+    pub system: Option<String>,
+    pub model: Option<String>,
+    pub tags: Vec<String>,
+    /// Extra request parameters, written to the `params` frontmatter key.
+    pub params: Map<String, Value>,
+    /// Text of the first `user:` entry.
+    pub message: Option<String>,
+    /// Persona written to the `persona` frontmatter key.
+    pub persona: Option<String>,
+    /// Profile written to the `profile` frontmatter key.
+    pub profile: Option<String>,
+}
+
+pub fn handle_create_subcommand(matches: &ArgMatches) -> Result<()> {
     let name = matches.get_one::<String>("name").unwrap();
     let dir = matches.get_one::<String>("dir").map(String::as_str);
 
     if let Some(directory) = dir {
         if !validate_directory(directory) {
-            eprintln!("Error: Directory does not exist.");
-            std::process::exit(1);
+            if !matches.get_flag("parents") {
+                return Err(Error::Usage(
+                    "Directory does not exist. Use --parents to create it.".to_string(),
+                ));
+            }
+            fs::create_dir_all(directory)
+                .map_err(|e| Error::io(format!("Error creating directory {}", directory), e))?;
         }
     }
 
-    if let Err(e) = create_chat(name, dir) {
-        eprintln!("Error creating chat: {}", e);
-        std::process::exit(1);
+    let mut options = match matches.get_one::<String>("template") {
+        Some(template) => {
+            let vars = parse_vars(matches).map_err(Error::Usage)?;
+            render_template(template, &vars).map_err(|e| {
+                Error::Config(format!("Error applying template '{}': {}", template, e))
+            })?
+        }
+        None => ChatOptions::default(),
+    };
+    apply_flags(matches, &mut options);
+
+    let path =
+        create_chat(name, dir, &options, matches.get_flag("force")).map_err(|e| match e {
+            Error::Io { source, .. } if source.kind() == io::ErrorKind::AlreadyExists => {
+                Error::Usage(format!("{} (use --force to overwrite it)", source))
+            }
+            e => e.context("Error creating chat"),
+        })?;
+    println!("Created {}", path.display());
+    Ok(())
+}
+
+/// Lets `--persona`, `--model`, `--system`, `--tag` and `--message` override a template.
+fn apply_flags(matches: &ArgMatches, options: &mut ChatOptions) {
+    if let Some(persona) = matches.get_one::<String>("persona") {
+        options.persona = Some(persona.clone());
     }
+    if let Some(profile) = profile_flag() {
+        options.profile = Some(profile.to_string());
+    }
+    if let Some(model) = matches.get_one::<String>("model") {
+        options.model = Some(model.clone());
+    }
+    if let Some(system) = matches.get_one::<String>("system") {
+        options.system = Some(system.clone());
+    }
+    for tag in matches.get_many::<String>("tag").unwrap_or_default() {
+        if !options.tags.contains(tag) {
+            options.tags.push(tag.clone());
+        }
+    }
+    if let Some(message) = matches.get_one::<String>("message") {
+        options.message = Some(message.clone());
+    }
+}
+
+/// Parses repeated `--var key=value` arguments.
+fn parse_vars(matches: &ArgMatches) -> std::result::Result<HashMap<String, String>, String> {
+    matches
+        .get_many::<String>("var")
+        .unwrap_or_default()
+        .map(|var| {
+            var.split_once('=')
+                .map(|(key, value)| (key.trim().to_string(), value.to_string()))
+                .ok_or_else(|| format!("Invalid --var '{}', expected key=value.", var))
+        })
+        .collect()
 }
 
-pub fn create_chat(name: &str, dir: Option<&str>) -> io::Result<()> {
+/// Creates a chat file whose frontmatter uses `options` over the config defaults.
+///
+/// `name` becomes the title as given and is slugified for the file name. An
+/// existing file is only replaced when `overwrite` is set.
+pub fn create_chat(
+    name: &str,
+    dir: Option<&str>,
+    options: &ChatOptions,
+    overwrite: bool,
+) -> Result<PathBuf> {
     let created_at = Utc::now().to_rfc3339();
     let updated_at = created_at.clone();
 
-    // Convert name to title case for frontmatter
-    let title = name.to_string(); // Assuming name is already in title case or needs no conversion
-
-    // Use the frontmatter template from config
-    let frontmatter = get_frontmatter_from_config(&title, &created_at, &updated_at)?;
+    let title = name.strip_suffix(".md").unwrap_or(name).trim();
+    let slug = slugify(title);
+    if slug.is_empty() {
+        return Err(Error::Usage(format!(
+            "'{}' doesn't contain any characters usable in a file name",
+            name
+        )));
+    }
 
     let mut file_path = PathBuf::from(dir.unwrap_or("."));
-    file_path.push(format!("{}.md", name));
 
-    let mut file = File::create(file_path)?;
-    file.write_all(frontmatter.as_bytes())?;
-    Ok(())
+    // Use the frontmatter template from the config that applies to the directory
+    let mut content =
+        get_frontmatter_from_config(&file_path, title, &created_at, &updated_at, options)?;
+    if let Some(message) = &options.message {
+        content.push_str(&format!("\nuser:\n{}\n", message));
+    }
+
+    file_path.push(format!("{}.md", slug));
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(overwrite)
+        .truncate(overwrite)
+        .create_new(!overwrite)
+        .open(&file_path)
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::AlreadyExists => {
+                io::Error::new(e.kind(), format!("{} already exists", file_path.display()))
+            }
+            _ => e,
+        })?;
+    file.write_all(content.as_bytes())?;
+    Ok(file_path)
+}
+
+/// Turns a title like "Q3 design review" into a file name like "q3-design-review".
+pub fn slugify(title: &str) -> String {
+    let mut slug = String::new();
+    for c in title.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() || c == '_' {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
 }
 
 pub fn validate_directory(dir: &str) -> bool {
@@ -51,7 +174,7 @@ pub fn create_command() -> Command {
         .about("Create a new chat file")
         .arg(
             Arg::new("name")
-                .help("Name of the chat file")
+                .help("Title of the chat; the file name is derived from it")
                 .required(true)
                 .num_args(1),
         )
@@ -61,35 +184,133 @@ pub fn create_command() -> Command {
                 .help("Directory to create the file in")
                 .num_args(1),
         )
+        .arg(
+            Arg::new("parents")
+                .long("parents")
+                .short('p')
+                .help("Create the --dir directory and its parents if missing")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("force")
+                .long("force")
+                .help("Overwrite the chat file if it already exists")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("persona")
+                .long("persona")
+                .help("Persona to use for the system prompt, model and params")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("model")
+                .long("model")
+                .help("Model to put in the frontmatter")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("system")
+                .long("system")
+                .help("System prompt to put in the frontmatter")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("tag")
+                .long("tag")
+                .help("Tag to add to the frontmatter (repeatable)")
+                .action(clap::ArgAction::Append)
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("message")
+                .long("message")
+                .help("Text of the first user message")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("template")
+                .long("template")
+                .help("Start from a template in .samvada/templates or ~/.samvada/templates")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("var")
+                .long("var")
+                .help("Fill a template {{placeholder}}, as key=value (repeatable)")
+                .requires("template")
+                .action(clap::ArgAction::Append)
+                .num_args(1),
+        )
+}
+
+/// Indents continuation lines so multi-line values stay inside their key.
+fn frontmatter_value(value: &str) -> String {
+    value.trim().replace('\n', "\n  ")
 }
 
 fn get_frontmatter_from_config(
+    dir: &Path,
     title: &str,
     created_at: &str,
     updated_at: &str,
-) -> io::Result<String> {
-    let app_config = match load_config() {
-        Ok(cfg) => cfg,
-        Err(e) => {
-            eprintln!("Error loading config: {}", e);
-            std::process::exit(1);
-        }
+    options: &ChatOptions,
+) -> Result<String> {
+    let app_config = load_config_for(dir)?;
+
+    // The profile picks the endpoint; `profile:` is only written when chosen with --profile
+    let profile_name = options.profile.as_ref().or(app_config.profile.as_ref());
+    let profile = match profile_name {
+        Some(name) => Some(app_config.find_profile(name).map_err(Error::Config)?),
+        None => None,
     };
 
-    // Use the loaded configurations
-    let system_prompt = app_config.system_prompt;
-    let model = app_config.model;
-    let api_endpoint = app_config.api_endpoint;
+    // A persona keeps its system prompt out of the file, so it is resolved when asking
+    let persona = match &options.persona {
+        Some(name) => Some(find_persona(name, &app_config.personas).map_err(Error::Config)?),
+        None => None,
+    };
+
+    // Options from flags or templates win over the persona and the loaded configurations
+    let system_prompt = match &persona {
+        Some(_) => options.system.clone().unwrap_or_default(),
+        None => options
+            .system
+            .clone()
+            .unwrap_or_else(|| app_config.system_prompt.clone()),
+    };
+    let model = options
+        .model
+        .clone()
+        .or_else(|| persona.as_ref().and_then(|persona| persona.model.clone()))
+        .or_else(|| profile.and_then(|profile| profile.model.clone()))
+        .unwrap_or_else(|| app_config.model.clone());
+    let api_endpoint = profile
+        .and_then(|profile| profile.api_endpoint.clone())
+        .unwrap_or_else(|| app_config.api_endpoint.clone());
+    let tags = format!("[{}]", options.tags.join(", "));
 
-    let frontmatter = FRONTMATTER_TEMPLATE
+    let mut frontmatter = FRONTMATTER_TEMPLATE
         .replace("{title}", title)
-        .replace("{system}", &system_prompt)
+        .replace("{system}", &frontmatter_value(&system_prompt))
         .replace("{model}", &model)
         .replace("{api_endpoint}", &api_endpoint)
         .replace("{created_at}", created_at)
         .replace("{updated_at}", updated_at)
-        .replace("{tags}", "[]")
+        .replace("{tags}", &tags)
         .replace("{summary}", "");
 
+    let closing = frontmatter.rfind("---").unwrap_or(frontmatter.len());
+    if !options.params.is_empty() {
+        let params = serde_json::to_string(&options.params).unwrap_or_default();
+        frontmatter.insert_str(closing, &format!("params: {}\n", params));
+    }
+    if let Some(persona) = &options.persona {
+        frontmatter.insert_str(closing, &format!("persona: {}\n", persona));
+    }
+    if let Some(profile) = &options.profile {
+        frontmatter.insert_str(closing, &format!("profile: {}\n", profile));
+    }
+
     Ok(frontmatter)
 }
@@ -0,0 +1,147 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::chat::config::get_config_dir;
+
+/// Name of the credential store in `~/.samvada`.
+const CREDENTIALS_FILE_NAME: &str = "credentials";
+
+/// API keys saved with `samvada auth login`, by name.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Credentials {
+    #[serde(default)]
+    pub keys: BTreeMap<String, Credential>,
+}
+
+/// One saved API key and the endpoint host it is used for.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Credential {
+    pub host: String,
+    pub api_key: String,
+}
+
+/// Returns the path of the credential store.
+pub fn credentials_path() -> io::Result<PathBuf> {
+    Ok(get_config_dir()?.join(CREDENTIALS_FILE_NAME))
+}
+
+impl Credentials {
+    /// Loads the credential store; a missing file is an empty store.
+    pub fn load() -> io::Result<Credentials> {
+        let path = credentials_path()?;
+        if !path.exists() {
+            return Ok(Credentials::default());
+        }
+        let content = fs::read_to_string(&path)?;
+        serde_json::from_str(&content).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is not a valid credential store: {}", path.display(), e),
+            )
+        })
+    }
+
+    /// Writes the store, readable and writable only by the current user.
+    pub fn save(&self) -> io::Result<PathBuf> {
+        let path = credentials_path()?;
+        let mut content = serde_json::to_string_pretty(self)?;
+        content.push('\n');
+        write_private_file(&path, content.as_bytes())?;
+        Ok(path)
+    }
+
+    /// Finds the key to use: the one named `name` if given, otherwise the one
+    /// saved for `host` (preferring a key named after the host).
+    pub fn find(&self, name: Option<&str>, host: &str) -> Option<(&str, &Credential)> {
+        if let Some(name) = name {
+            return self
+                .keys
+                .get_key_value(name)
+                .map(|(name, credential)| (name.as_str(), credential));
+        }
+        self.keys
+            .get_key_value(host)
+            .filter(|(_, credential)| credential.host == host)
+            .or_else(|| {
+                self.keys
+                    .iter()
+                    .find(|(_, credential)| credential.host == host)
+            })
+            .map(|(name, credential)| (name.as_str(), credential))
+    }
+}
+
+/// Replaces `path` with `content`, readable and writable only by the current user.
+///
+/// The content goes to a temporary file in the same directory that is synced
+/// and then renamed over `path`, so a failed write leaves the old file intact.
+pub fn write_private_file(path: &Path, content: &[u8]) -> io::Result<()> {
+    replace_file(path, content, true)
+}
+
+/// Replaces `path` with `content` like `write_private_file`, keeping the file's permissions.
+pub fn write_file_atomically(path: &Path, content: &[u8]) -> io::Result<()> {
+    replace_file(path, content, false)
+}
+
+fn replace_file(path: &Path, content: &[u8], private: bool) -> io::Result<()> {
+    // A bare file name has an empty parent
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = dir.join(format!(".{}.{}.tmp", name, std::process::id()));
+    // Left behind by a crash
+    let _ = fs::remove_file(&temp);
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    if private {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let written = options.open(&temp).and_then(|mut file| {
+        if !private {
+            if let Ok(metadata) = fs::metadata(path) {
+                file.set_permissions(metadata.permissions())?;
+            }
+        }
+        file.write_all(content)?;
+        file.sync_all()?;
+        fs::rename(&temp, path)
+    });
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    written?;
+
+    // Make the rename itself durable
+    #[cfg(unix)]
+    fs::File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+/// Returns the host of an endpoint URL, or the value itself if it is a bare host.
+pub fn endpoint_host(endpoint: &str) -> String {
+    reqwest::Url::parse(endpoint)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_else(|| endpoint.trim().to_string())
+}
+
+/// Hides all but the start and end of a key, e.g. `sk-…a1b2`.
+pub fn mask_key(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    if chars.len() <= 10 {
+        return "*".repeat(chars.len());
+    }
+    let start: String = chars[..3].iter().collect();
+    let end: String = chars[chars.len() - 4..].iter().collect();
+    format!("{}…{}", start, end)
+}
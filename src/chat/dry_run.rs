@@ -0,0 +1,101 @@
+//! `--dry-run`: shows the request `chat ask` or `chat quick` would send, without sending it.
+
+use serde_json::Value;
+
+use crate::chat::api::build_request_body;
+use crate::chat::config::AppConfig;
+use crate::chat::parser::ParsedChat;
+
+/// Rough number of characters per token in English text and code.
+const CHARS_PER_TOKEN: u64 = 4;
+
+/// Tokens each message adds for its role and separators.
+const TOKENS_PER_MESSAGE: u64 = 4;
+
+/// Tokens that prime the assistant's reply.
+const REPLY_PRIMING_TOKENS: u64 = 3;
+
+/// Prints the request body as JSON or as readable text, followed by token and cost estimates.
+///
+/// The estimates go to stderr, so the JSON body can be piped on its own.
+pub fn print_dry_run(
+    chat: &ParsedChat,
+    api_messages: Vec<Value>,
+    format: &str,
+    config: &AppConfig,
+) {
+    let prompt_tokens = estimate_tokens(&api_messages);
+    let body = build_request_body(&chat.model, api_messages, &chat.params);
+
+    match format {
+        "text" => print_text(&chat.api_endpoint, &body),
+        _ => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&body).unwrap_or_default()
+            )
+        }
+    }
+
+    eprintln!();
+    eprintln!("Endpoint: {}", chat.api_endpoint);
+    eprintln!("Estimated prompt tokens: ~{}", prompt_tokens);
+    let max_tokens = ["max_completion_tokens", "max_tokens"]
+        .iter()
+        .find_map(|key| chat.params.get(*key).and_then(Value::as_u64));
+    match (config.price_for(&chat.model), max_tokens) {
+        (Some(price), Some(max_tokens)) => eprintln!(
+            "Estimated cost: ~${:.6} for the prompt, up to ${:.6} with {} completion tokens",
+            price.cost(prompt_tokens, 0),
+            price.cost(prompt_tokens, max_tokens),
+            max_tokens
+        ),
+        (Some(price), None) => eprintln!(
+            "Estimated cost: ~${:.6} for the prompt, plus the answer",
+            price.cost(prompt_tokens, 0)
+        ),
+        (None, _) => eprintln!(
+            "Estimated cost: unknown, '{}' has no price (see `pricing:` in config.yaml)",
+            chat.model
+        ),
+    }
+    eprintln!("Dry run: nothing was sent.");
+}
+
+/// Estimates the prompt tokens of a request from the length of its messages.
+///
+/// This is a heuristic of about four characters per token; the real count
+/// depends on the model's tokenizer.
+pub fn estimate_tokens(api_messages: &[Value]) -> u64 {
+    let message_tokens: u64 = api_messages
+        .iter()
+        .map(|message| {
+            estimate_text_tokens(message["content"].as_str().unwrap_or_default())
+                + TOKENS_PER_MESSAGE
+        })
+        .sum();
+    message_tokens + REPLY_PRIMING_TOKENS
+}
+
+/// Estimates the tokens of a piece of text, at about four characters per token.
+pub fn estimate_text_tokens(text: &str) -> u64 {
+    (text.chars().count() as u64).div_ceil(CHARS_PER_TOKEN)
+}
+
+/// Prints the endpoint, the parameters and each message under a `--- role ---` header.
+fn print_text(api_endpoint: &str, body: &Value) {
+    println!("POST {}", api_endpoint);
+    if let Some(params) = body.as_object() {
+        for (key, value) in params.iter().filter(|(key, _)| *key != "messages") {
+            match value.as_str() {
+                Some(text) => println!("{}: {}", key, text),
+                None => println!("{}: {}", key, value),
+            }
+        }
+    }
+    for message in body["messages"].as_array().into_iter().flatten() {
+        println!();
+        println!("--- {} ---", message["role"].as_str().unwrap_or("unknown"));
+        println!("{}", message["content"].as_str().unwrap_or_default());
+    }
+}
@@ -0,0 +1,227 @@
+use clap::{Arg, ArgMatches, Command};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::chat::lint::frontmatter::template_keys;
+use crate::chat::lint::walk::{collect_markdown_files, is_chat_file};
+use crate::chat::lint::{code_lines, entry_role, find_frontmatter, is_metadata_comment};
+use crate::chat::parser::split_frontmatter_key;
+use crate::error::Error;
+
+/// Handles the fmt subcommand, rewriting chat files or checking that they are formatted.
+pub fn handle_fmt_subcommand(matches: &ArgMatches) -> Result<(), Error> {
+    let path = matches.get_one::<String>("path").unwrap();
+    let check = matches.get_flag("check");
+
+    let files = chat_files(Path::new(path))
+        .map_err(|e| Error::Usage(format!("Error formatting chat: {}", e)))?;
+
+    let mut unformatted = 0;
+    for file in files {
+        let content = fs::read_to_string(&file)
+            .map_err(|e| Error::io(format!("Failed to read {}", file.display()), e))?;
+        let Some(formatted) = format_chat(&content) else {
+            eprintln!("Skipping {}: no frontmatter found.", file.display());
+            continue;
+        };
+        if formatted == content {
+            continue;
+        }
+
+        unformatted += 1;
+        if check {
+            println!("Would reformat {}", file.display());
+        } else {
+            fs::write(&file, formatted)
+                .map_err(|e| Error::io(format!("Failed to write {}", file.display()), e))?;
+            println!("Formatted {}", file.display());
+        }
+    }
+
+    if check && unformatted > 0 {
+        return Err(Error::CheckFailed);
+    }
+    Ok(())
+}
+
+/// Returns the file itself, or the chat files found recursively in a directory.
+///
+/// A file that isn't a chat is skipped with a warning, so other markdown is never rewritten.
+fn chat_files(path: &Path) -> Result<Vec<PathBuf>, String> {
+    if path.is_file() {
+        if !read_chat(path) {
+            eprintln!("Skipping {}: not a chat file.", path.display());
+            return Ok(Vec::new());
+        }
+        return Ok(vec![path.to_path_buf()]);
+    }
+    if !path.is_dir() {
+        return Err("Invalid path: Not a file or directory.".to_string());
+    }
+
+    Ok(collect_markdown_files(path, &[], &[])?
+        .into_iter()
+        .filter(|file| read_chat(file))
+        .collect())
+}
+
+fn read_chat(path: &Path) -> bool {
+    fs::read_to_string(path)
+        .map(|content| is_chat_file(&content))
+        .unwrap_or(false)
+}
+
+/// Re-emits a chat file in the canonical layout, or `None` if it has no frontmatter.
+///
+/// Frontmatter keys follow the `FRONTMATTER_TEMPLATE` order, with any other keys
+/// after them. Each role marker sits alone on its line, followed by its content;
+/// metadata comments close their assistant entry after a blank line, and entries
+/// are separated by exactly one blank line. Message text is never changed.
+pub fn format_chat(content: &str) -> Option<String> {
+    let lines: Vec<&str> = content.lines().collect();
+    let (start, end) = find_frontmatter(&lines)?;
+
+    let mut output: Vec<String> = lines[..start].iter().map(|line| line.to_string()).collect();
+    output.push("---".to_string());
+    output.extend(format_frontmatter(&lines[start + 1..end]));
+    output.push("---".to_string());
+
+    let blocks = format_body(&lines[end + 1..]);
+    for (index, block) in blocks.into_iter().enumerate() {
+        if index > 0 {
+            output.push(String::new());
+        }
+        output.extend(block);
+    }
+
+    Some(output.join("\n") + "\n")
+}
+
+/// Orders frontmatter keys by the template and normalises `key: value` spacing.
+fn format_frontmatter(block: &[&str]) -> Vec<String> {
+    let mut leading = Vec::new();
+    let mut groups: Vec<(String, Vec<String>)> = Vec::new();
+
+    for line in block {
+        if let Some((key, value)) = split_frontmatter_key(line) {
+            let value = value.trim();
+            let key_line = if value.is_empty() {
+                format!("{}:", key)
+            } else {
+                format!("{}: {}", key, value)
+            };
+            groups.push((key.to_string(), vec![key_line]));
+        } else if let Some((_, group)) = groups.last_mut() {
+            group.push(line.to_string());
+        } else {
+            leading.push(line.to_string());
+        }
+    }
+
+    let order = template_keys();
+    let rank = |key: &str| {
+        order
+            .iter()
+            .position(|known| *known == key)
+            .unwrap_or(order.len())
+    };
+    // Stable, so keys outside the template keep their relative order.
+    groups.sort_by_key(|(key, _)| rank(key));
+
+    leading
+        .into_iter()
+        .chain(groups.into_iter().flat_map(|(_, lines)| lines))
+        .collect()
+}
+
+/// Splits the body into blocks: an optional preamble, then one block per entry.
+fn format_body(body: &[&str]) -> Vec<Vec<String>> {
+    let mut preamble: Vec<&str> = Vec::new();
+    let mut entries: Vec<(&'static str, Vec<&str>)> = Vec::new();
+
+    for line in body {
+        if let Some(role) = entry_role(line) {
+            let inline = line.split_once(':').map_or("", |(_, rest)| rest.trim());
+            let mut content = Vec::new();
+            if !inline.is_empty() {
+                content.push(inline);
+            }
+            entries.push((role, content));
+        } else if let Some((_, content)) = entries.last_mut() {
+            content.push(line);
+        } else {
+            preamble.push(line);
+        }
+    }
+
+    let mut blocks = Vec::new();
+    let preamble = trim_blank_lines(&preamble);
+    if !preamble.is_empty() {
+        blocks.push(preamble.iter().map(|line| line.to_string()).collect());
+    }
+    for (role, content) in entries {
+        blocks.push(format_entry(role, &content));
+    }
+    blocks
+}
+
+/// Formats one entry: the marker line, its content, then any trailing metadata.
+fn format_entry(role: &str, content: &[&str]) -> Vec<String> {
+    let mut content = trim_blank_lines(content);
+    let mut metadata = Vec::new();
+
+    if role == "assistant" {
+        // Comments inside a code block are part of the answer
+        let code = code_lines(content);
+        while let Some(line) = content.last() {
+            if is_metadata_comment(line) && !code[content.len() - 1] {
+                metadata.push(*line);
+            } else if !line.trim().is_empty() {
+                break;
+            }
+            content = &content[..content.len() - 1];
+        }
+        metadata.reverse();
+    }
+
+    let mut block = vec![format!("{}:", role)];
+    block.extend(content.iter().map(|line| line.to_string()));
+    if !metadata.is_empty() {
+        if !content.is_empty() {
+            block.push(String::new());
+        }
+        block.extend(metadata.iter().map(|line| line.trim().to_string()));
+    }
+    block
+}
+
+/// Drops blank lines from both ends of a slice of lines.
+fn trim_blank_lines<'a, 'b>(lines: &'b [&'a str]) -> &'b [&'a str] {
+    let start = lines
+        .iter()
+        .position(|line| !line.trim().is_empty())
+        .unwrap_or(lines.len());
+    let end = lines
+        .iter()
+        .rposition(|line| !line.trim().is_empty())
+        .map_or(start, |index| index + 1);
+    &lines[start..end]
+}
+
+/// Defines the fmt command for the CLI application.
+pub fn fmt_command() -> Command {
+    Command::new("fmt")
+        .about("Format chat files in the canonical layout")
+        .arg(
+            Arg::new("path")
+                .help("Path to the file or directory")
+                .required(true)
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("check")
+                .long("check")
+                .help("Don't write files; exit with status 1 if any would be reformatted")
+                .action(clap::ArgAction::SetTrue),
+        )
+}
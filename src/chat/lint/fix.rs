@@ -0,0 +1,257 @@
+use chrono::Utc;
+use similar::TextDiff;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use super::frontmatter::{entries, missing_keys};
+use super::rules::{reports, Suppressions};
+use super::{
+    code_lines, entry_role, find_frontmatter, is_metadata_comment, near_miss_role,
+    role_marker_regex,
+};
+use crate::chat::config::{load_config_for, LintConfig};
+
+/// Applies every safe, mechanical fix to a chat file's content.
+///
+/// Problems that cannot be fixed without guessing the author's intent are left
+/// alone so that linting the result still reports them, as are problems whose
+/// rule is off in `config` or suppressed by a `samvada-lint-disable` comment.
+pub fn fix_content(content: &str, file_path: &Path, config: &LintConfig) -> String {
+    let original: Vec<&str> = content.lines().collect();
+    let Some((start, end)) = find_frontmatter(&original) else {
+        return content.to_string();
+    };
+    let suppressions = Suppressions::parse(content);
+    let enabled = |rule: &str, line: usize| reports(rule, line, config, &suppressions);
+
+    let missing_keys = if enabled("frontmatter-key", start + 1) {
+        missing_keys(&entries(&original, start, end))
+    } else {
+        Vec::new()
+    };
+
+    let mut lines: Vec<String> = original.iter().map(|line| line.to_string()).collect();
+    let body = lines.split_off(end + 1);
+    // 1-based line number of the first body line; the fixes before the last keep line numbers.
+    let first_line = end + 2;
+    let last_line = body
+        .iter()
+        .rposition(|line| !line.trim().is_empty())
+        .map_or(end + 1, |index| first_line + index);
+
+    let body = fix_role_markers(body, first_line, &enabled);
+    let body = fix_metadata_placement(body, first_line, &enabled);
+    let body = if enabled("last-entry-user", last_line) {
+        fix_trailing_user(body)
+    } else {
+        body
+    };
+
+    let closing = lines.pop().expect("frontmatter has a closing delimiter");
+    lines.extend(missing_key_lines(&missing_keys, file_path));
+    lines.push(closing);
+    lines.extend(body);
+
+    let mut fixed = lines.join("\n");
+    if content.ends_with('\n') || content.is_empty() {
+        fixed.push('\n');
+    }
+    fixed
+}
+
+/// Renders a unified diff between the original and fixed content.
+pub fn unified_diff(original: &str, fixed: &str, file_path: &Path) -> String {
+    let name = file_path.display().to_string();
+    TextDiff::from_lines(original, fixed)
+        .unified_diff()
+        .header(&format!("a/{}", name), &format!("b/{}", name))
+        .to_string()
+}
+
+/// Builds `key: value` lines for missing frontmatter keys, using config defaults.
+fn missing_key_lines(keys: &[&str], file_path: &Path) -> Vec<String> {
+    if keys.is_empty() {
+        return Vec::new();
+    }
+
+    let config = load_config_for(file_path).ok();
+    let now = Utc::now().to_rfc3339();
+    let title = file_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    keys.iter()
+        .filter_map(|key| {
+            let value = match *key {
+                "title" => title.clone(),
+                "system" => config.as_ref()?.system_prompt.clone(),
+                "model" => config.as_ref()?.model.clone(),
+                "api_endpoint" => config.as_ref()?.api_endpoint.clone(),
+                "created_at" | "updated_at" => now.clone(),
+                "tags" => "[]".to_string(),
+                "summary" => String::new(),
+                _ => return None,
+            };
+            Some(format!("{}: {}", key, value).trim_end().to_string())
+        })
+        .collect()
+}
+
+/// Rewrites role markers like `  user :` to `user:`, keeping the text after the colon.
+///
+/// Lines inside fenced code blocks are left alone.
+fn fix_role_markers(
+    body: Vec<String>,
+    first_line: usize,
+    enabled: &dyn Fn(&str, usize) -> bool,
+) -> Vec<String> {
+    let code = code_lines(&body);
+    body.into_iter()
+        .zip(code)
+        .enumerate()
+        .map(|(index, (line, in_code))| match near_miss_role(&line) {
+            Some(role) if !in_code && enabled("role-marker", first_line + index) => {
+                let marker_end = role_marker_regex()
+                    .find(&line)
+                    .expect("near_miss_role matched this line")
+                    .end();
+                format!("{}:{}", role, &line[marker_end..])
+            }
+            _ => line,
+        })
+        .collect()
+}
+
+/// Moves misplaced metadata comments to the end of the assistant entry they belong to.
+///
+/// Comments found in a user entry belong to the closest preceding assistant entry;
+/// if there is none, they are left where they are.
+fn fix_metadata_placement(
+    body: Vec<String>,
+    first_line: usize,
+    enabled: &dyn Fn(&str, usize) -> bool,
+) -> Vec<String> {
+    let mut entries = split_entries(body);
+    // Line number of each entry's first line, before anything is moved
+    let starts: Vec<usize> = entries
+        .iter()
+        .scan(first_line, |line, entry| {
+            let start = *line;
+            *line += entry.len();
+            Some(start)
+        })
+        .collect();
+    let movable = |entry: usize, index: usize| enabled("metadata-placement", starts[entry] + index);
+    // Per assistant entry: comments misplaced inside it, and comments found in later user entries.
+    let mut moved: BTreeMap<usize, (Vec<String>, Vec<String>)> = BTreeMap::new();
+    let mut last_assistant = None;
+
+    for (index, entry) in entries.iter_mut().enumerate() {
+        let role = entry.first().and_then(|line| entry_role(line));
+
+        match role {
+            Some("assistant") => {
+                last_assistant = Some(index);
+                let trailing_start = trailing_metadata_start(entry);
+                let misplaced =
+                    take_metadata(entry, 1..trailing_start, |line| movable(index, line));
+                if !misplaced.is_empty() {
+                    moved.entry(index).or_default().0.extend(misplaced);
+                }
+            }
+            Some("user") => {
+                let Some(target) = last_assistant else {
+                    continue;
+                };
+                let misplaced = take_metadata(entry, 1..entry.len(), |line| movable(index, line));
+                if !misplaced.is_empty() {
+                    moved.entry(target).or_default().1.extend(misplaced);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for (index, (own, later)) in moved {
+        let entry = &mut entries[index];
+        let trailing_start = trailing_metadata_start(entry);
+        let trailing: Vec<String> = entry
+            .drain(trailing_start..)
+            .filter(|line| !line.trim().is_empty())
+            .collect();
+        while entry.len() > 1 && entry.last().is_some_and(|line| line.trim().is_empty()) {
+            entry.pop();
+        }
+
+        entry.push(String::new());
+        entry.extend(own);
+        entry.extend(trailing);
+        entry.extend(later);
+        entry.push(String::new());
+    }
+
+    entries.into_iter().flatten().collect()
+}
+
+/// Appends an empty `user:` entry when the chat ends with an assistant answer.
+fn fix_trailing_user(mut body: Vec<String>) -> Vec<String> {
+    let last_role = body.iter().rev().find_map(|line| entry_role(line));
+
+    if last_role == Some("assistant") {
+        while body.last().is_some_and(|line| line.trim().is_empty()) {
+            body.pop();
+        }
+        body.push(String::new());
+        body.push("user:".to_string());
+    }
+    body
+}
+
+/// Splits body lines into entries; each entry but a leading preamble starts with its role marker.
+fn split_entries(body: Vec<String>) -> Vec<Vec<String>> {
+    let mut entries: Vec<Vec<String>> = vec![Vec::new()];
+    for line in body {
+        if entry_role(&line).is_some() {
+            entries.push(Vec::new());
+        }
+        entries.last_mut().unwrap().push(line);
+    }
+    entries
+}
+
+/// Index where the entry's trailing run of metadata comments (and blank lines) starts.
+fn trailing_metadata_start(entry: &[String]) -> usize {
+    let code = code_lines(entry);
+    let mut start = entry.len();
+    while start > 1 {
+        let line = &entry[start - 1];
+        if line.trim().is_empty() || (is_metadata_comment(line) && !code[start - 1]) {
+            start -= 1;
+        } else {
+            break;
+        }
+    }
+    start
+}
+
+/// Removes and returns the metadata comments in `range` that aren't inside a code block
+/// and may be moved.
+fn take_metadata(
+    entry: &mut Vec<String>,
+    range: std::ops::Range<usize>,
+    movable: impl Fn(usize) -> bool,
+) -> Vec<String> {
+    let code = code_lines(entry);
+    let mut taken = Vec::new();
+    let mut kept = Vec::new();
+    for (index, line) in entry.drain(..).enumerate() {
+        if range.contains(&index) && !code[index] && is_metadata_comment(&line) && movable(index) {
+            taken.push(line);
+        } else {
+            kept.push(line);
+        }
+    }
+    *entry = kept;
+    taken
+}
@@ -0,0 +1,199 @@
+use chrono::{DateTime, FixedOffset};
+use regex::Regex;
+use reqwest::Url;
+
+use super::Range;
+use crate::chat::config::LintConfig;
+use crate::chat::constants::{FRONTMATTER_TEMPLATE, OPTIONAL_FRONTMATTER_KEYS};
+use crate::chat::parser::{parse_frontmatter_entries, parse_params, FrontmatterEntry};
+
+/// Returns the keys of `FRONTMATTER_TEMPLATE`, in template order.
+pub fn template_keys() -> Vec<&'static str> {
+    let key_pattern = Regex::new(r"\{(\w+)\}").expect("Failed to compile template key regex.");
+    key_pattern
+        .captures_iter(FRONTMATTER_TEMPLATE)
+        .map(|cap| cap.get(1).unwrap().as_str())
+        .collect()
+}
+
+/// Parses the frontmatter between the delimiter lines `start` and `end` (0-based).
+pub fn entries(lines: &[&str], start: usize, end: usize) -> Vec<FrontmatterEntry> {
+    parse_frontmatter_entries(&lines[start + 1..end], start + 1)
+}
+
+/// Returns the template keys that are not set in the frontmatter.
+pub fn missing_keys(entries: &[FrontmatterEntry]) -> Vec<&'static str> {
+    template_keys()
+        .into_iter()
+        .filter(|key| !entries.iter().any(|entry| entry.key == *key))
+        .collect()
+}
+
+/// Validates the keys and values of the frontmatter between lines `start` and `end`.
+pub fn validate_frontmatter(
+    lines: &[&str],
+    start: usize,
+    end: usize,
+    config: &LintConfig,
+    push: &mut impl FnMut(&'static str, String, Range),
+) {
+    let entries = entries(lines, start, end);
+    let template_keys = template_keys();
+    let range_of = |entry: &FrontmatterEntry| Range::line(entry.line + 1, lines[entry.line]);
+    // A persona supplies the system prompt, so `system:` may stay empty
+    let has_persona = entries
+        .iter()
+        .any(|entry| entry.key == "persona" && !entry.value.is_empty());
+
+    for key in missing_keys(&entries) {
+        push(
+            "frontmatter-key",
+            format!("Frontmatter error: '{}' is missing.", key),
+            Range::lines(lines, start, end),
+        );
+    }
+
+    for entry in &entries {
+        let key = entry.key.as_str();
+        if !template_keys.contains(&key) && !OPTIONAL_FRONTMATTER_KEYS.contains(&key) {
+            push(
+                "frontmatter-unknown-key",
+                format!("Frontmatter warning: unknown key '{}'.", entry.key),
+                range_of(entry),
+            );
+            continue;
+        }
+
+        if entry.value.is_empty() {
+            let allowed = config.allow_empty.contains(&entry.key)
+                || OPTIONAL_FRONTMATTER_KEYS.contains(&key)
+                || (key == "system" && has_persona);
+            if !allowed {
+                push(
+                    "frontmatter-empty-value",
+                    format!("Frontmatter error: '{}' has no value.", entry.key),
+                    range_of(entry),
+                );
+            }
+            continue;
+        }
+
+        match entry.key.as_str() {
+            "created_at" | "updated_at" => {
+                if let Err(e) = DateTime::parse_from_rfc3339(&entry.value) {
+                    push(
+                        "frontmatter-timestamp",
+                        format!(
+                            "Frontmatter error: '{}' is not an RFC 3339 timestamp ({}).",
+                            entry.key, e
+                        ),
+                        range_of(entry),
+                    );
+                }
+            }
+            "api_endpoint" => {
+                if let Some(problem) = endpoint_problem(&entry.value) {
+                    push(
+                        "frontmatter-endpoint",
+                        format!("Frontmatter error: 'api_endpoint' {}.", problem),
+                        range_of(entry),
+                    );
+                }
+            }
+            "params" => {
+                if let Err(e) = parse_params(&entry.value) {
+                    push(
+                        "frontmatter-params",
+                        format!("Frontmatter error: {}.", e),
+                        range_of(entry),
+                    );
+                }
+            }
+            "private" if !matches!(entry.value.as_str(), "true" | "false") => {
+                push(
+                    "frontmatter-private",
+                    "Frontmatter error: 'private' must be true or false.".to_string(),
+                    range_of(entry),
+                );
+            }
+            "tags" if !is_list(&entry.value) => {
+                push(
+                    "frontmatter-tags",
+                    "Frontmatter error: 'tags' must be a list, e.g. [rust, review].".to_string(),
+                    range_of(entry),
+                );
+            }
+            "model" if !is_known_model(&entry.value, &config.known_models) => {
+                push(
+                    "unknown-model",
+                    format!(
+                        "Frontmatter warning: model '{}' is not in lint.known_models.",
+                        entry.value
+                    ),
+                    range_of(entry),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    validate_timestamp_order(&entries, lines, push);
+}
+
+/// Reports `updated_at` values that are earlier than `created_at`.
+fn validate_timestamp_order(
+    entries: &[FrontmatterEntry],
+    lines: &[&str],
+    push: &mut impl FnMut(&'static str, String, Range),
+) {
+    let timestamp = |key: &str| -> Option<(&FrontmatterEntry, DateTime<FixedOffset>)> {
+        let entry = entries.iter().find(|entry| entry.key == key)?;
+        let parsed = DateTime::parse_from_rfc3339(&entry.value).ok()?;
+        Some((entry, parsed))
+    };
+
+    if let (Some((_, created)), Some((updated_entry, updated))) =
+        (timestamp("created_at"), timestamp("updated_at"))
+    {
+        if updated < created {
+            push(
+                "frontmatter-timestamp-order",
+                "Frontmatter error: 'updated_at' is earlier than 'created_at'.".to_string(),
+                Range::line(updated_entry.line + 1, lines[updated_entry.line]),
+            );
+        }
+    }
+}
+
+/// Describes what is wrong with an endpoint, or `None` for a valid http(s) URL.
+pub fn endpoint_problem(value: &str) -> Option<String> {
+    match Url::parse(value) {
+        Ok(url) if url.scheme() != "http" && url.scheme() != "https" => {
+            Some(format!("must use http or https, not '{}'", url.scheme()))
+        }
+        Ok(url) if url.host_str().is_none() => Some("has no host".to_string()),
+        Ok(_) => None,
+        Err(e) => Some(format!("is not a valid URL ({})", e)),
+    }
+}
+
+/// Accepts a flow list (`[a, b]`) or a block list (`- a` on following lines).
+fn is_list(value: &str) -> bool {
+    let flow = value.starts_with('[') && value.ends_with(']');
+    let block = value
+        .lines()
+        .all(|line| line == "-" || line.starts_with("- "));
+    flow || block
+}
+
+/// Matches a model against the known list; entries ending in `*` match by prefix.
+/// An empty list accepts every model.
+fn is_known_model(model: &str, known_models: &[String]) -> bool {
+    known_models.is_empty()
+        || known_models
+            .iter()
+            .any(|known| match known.strip_suffix('*') {
+                Some(prefix) => model.starts_with(prefix),
+                None => model == known,
+            })
+}
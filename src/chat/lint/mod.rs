@@ -0,0 +1,661 @@
+pub mod fix;
+pub mod frontmatter;
+pub mod output;
+pub mod rules;
+pub mod walk;
+
+pub use rules::{Severity, RULES};
+
+use crate::chat::config::{load_config_for, LintConfig};
+use crate::chat::constants::METADATA_KEYS;
+use crate::error::Error;
+use clap::{Arg, ArgMatches, Command};
+use rayon::prelude::*;
+use regex::Regex;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// A 1-based line/column span. `end_column` points one past the last character.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Range {
+    pub start_line: usize,
+    pub start_column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+impl Range {
+    /// Covers the whole of line `number` (1-based) whose text is `text`.
+    fn line(number: usize, text: &str) -> Self {
+        Range {
+            start_line: number,
+            start_column: 1,
+            end_line: number,
+            end_column: text.chars().count() + 1,
+        }
+    }
+
+    /// Covers `lines[start..=end]` (0-based indices).
+    fn lines(lines: &[&str], start: usize, end: usize) -> Self {
+        Range {
+            start_line: start + 1,
+            start_column: 1,
+            end_line: end + 1,
+            end_column: lines.get(end).map_or(0, |line| line.chars().count()) + 1,
+        }
+    }
+}
+
+/// A single problem found in a chat file.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub rule: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    pub path: PathBuf,
+    pub range: Range,
+}
+
+/// The files that were linted and everything found in them.
+#[derive(Debug, Default)]
+pub struct LintReport {
+    pub files: Vec<PathBuf>,
+    pub diagnostics: Vec<Diagnostic>,
+    /// Unified diffs of the rewrites made (or proposed, for a dry run) by `--fix`.
+    pub fixes: Vec<(PathBuf, String)>,
+}
+
+impl LintReport {
+    /// Appends another report, keeping files and diagnostics in order.
+    fn merge(&mut self, other: LintReport) {
+        self.files.extend(other.files);
+        self.diagnostics.extend(other.diagnostics);
+        self.fixes.extend(other.fixes);
+    }
+}
+
+/// Options for a `chat lint` run.
+#[derive(Debug, Clone)]
+pub struct LintOptions {
+    pub fix_mode: FixMode,
+    /// Globs a file must match, relative to the linted directory. Empty means all files.
+    pub include: Vec<String>,
+    /// Globs of files and directories to skip, relative to the linted directory.
+    pub exclude: Vec<String>,
+    pub config: LintConfig,
+}
+
+/// What `chat lint` should do with fixable problems.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FixMode {
+    Off,
+    Apply,
+    DryRun,
+}
+
+/// Output formats supported by `chat lint --format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Sarif,
+}
+
+impl OutputFormat {
+    fn from_arg(value: &str) -> Self {
+        match value {
+            "json" => OutputFormat::Json,
+            "sarif" => OutputFormat::Sarif,
+            _ => OutputFormat::Text,
+        }
+    }
+}
+
+/// Handles the lint subcommand based on provided CLI arguments.
+pub fn handle_lint_subcommand(matches: &ArgMatches) -> Result<(), Error> {
+    if matches.get_flag("list-rules") {
+        print_rules();
+        return Ok(());
+    }
+
+    let path = matches
+        .get_one::<String>("path")
+        .expect("Path argument is required.");
+    let format = OutputFormat::from_arg(matches.get_one::<String>("format").unwrap());
+    let fix_mode = match (matches.get_flag("fix"), matches.get_flag("dry-run")) {
+        (false, _) => FixMode::Off,
+        (true, false) => FixMode::Apply,
+        (true, true) => FixMode::DryRun,
+    };
+
+    if !is_valid_path(path) {
+        return Err(Error::Usage("Invalid path.".to_string()));
+    }
+
+    let cfg = load_config_for(Path::new(path))?;
+    let mut config = cfg.lint;
+    // The configured default model is always considered known.
+    if !config.known_models.is_empty() && !config.known_models.contains(&cfg.model) {
+        config.known_models.push(cfg.model);
+    }
+    for rule in rules::unknown_rules(&config) {
+        eprintln!("Warning: unknown lint rule '{}' in config.", rule);
+    }
+
+    let options = LintOptions {
+        fix_mode,
+        include: get_globs(matches, "include"),
+        exclude: get_globs(matches, "exclude"),
+        config,
+    };
+
+    let report = lint_path(path, &options)
+        .map_err(|e| Error::Usage(format!("Error linting chat: {}", e)))?;
+
+    for (file, diff) in &report.fixes {
+        match fix_mode {
+            // Keep stdout parseable when it carries a JSON or SARIF report
+            FixMode::DryRun if format == OutputFormat::Text => print!("{}", diff),
+            FixMode::DryRun => eprint!("{}", diff),
+            _ => eprintln!("Fixed {}", file.display()),
+        }
+    }
+
+    match format {
+        OutputFormat::Text => output::print_text(&report),
+        OutputFormat::Json => println!("{}", output::to_json(&report)),
+        OutputFormat::Sarif => println!("{}", output::to_sarif(&report)),
+    }
+
+    if report
+        .diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.severity == Severity::Error)
+    {
+        return Err(Error::CheckFailed);
+    }
+    Ok(())
+}
+
+/// Prints every rule with its default severity and description.
+fn print_rules() {
+    for rule in RULES {
+        println!(
+            "{:<24} {:<8} {}",
+            rule.id,
+            rule.default_severity.as_str(),
+            rule.description
+        );
+    }
+}
+
+fn get_globs(matches: &ArgMatches, id: &str) -> Vec<String> {
+    matches
+        .get_many::<String>(id)
+        .unwrap_or_default()
+        .cloned()
+        .collect()
+}
+
+/// Lints the provided path, which can be a file or directory.
+///
+/// Directories are walked recursively and only markdown files with samvada
+/// frontmatter are linted, in parallel. A file given explicitly is always linted.
+pub fn lint_path(path: &str, options: &LintOptions) -> Result<LintReport, String> {
+    let path = Path::new(path);
+
+    if path.is_file() {
+        Ok(lint_file(path, options, false).unwrap_or_default())
+    } else if path.is_dir() {
+        let files = walk::collect_markdown_files(path, &options.include, &options.exclude)?;
+        let reports: Vec<LintReport> = files
+            .par_iter()
+            .filter_map(|file| lint_file(file, options, true))
+            .collect();
+
+        let mut report = LintReport::default();
+        for file_report in reports {
+            report.merge(file_report);
+        }
+        Ok(report)
+    } else {
+        Err("Invalid path: Not a file or directory.".to_string())
+    }
+}
+
+/// Lints a single file, applying fixes if asked.
+///
+/// Returns `None` if `chat_files_only` is set and the file has no samvada frontmatter.
+fn lint_file(file_path: &Path, options: &LintOptions, chat_files_only: bool) -> Option<LintReport> {
+    let fix_mode = options.fix_mode;
+    let mut report = LintReport::default();
+
+    let mut content = match fs::read_to_string(file_path) {
+        Ok(content) => content,
+        Err(e) => {
+            report.files.push(file_path.to_path_buf());
+            report.diagnostics.push(Diagnostic {
+                rule: "unreadable-file",
+                severity: Severity::Error,
+                message: format!("Failed to read file: {}", e),
+                path: file_path.to_path_buf(),
+                range: Range::line(1, ""),
+            });
+            return Some(report);
+        }
+    };
+
+    if chat_files_only && !walk::is_chat_file(&content) {
+        return None;
+    }
+    report.files.push(file_path.to_path_buf());
+
+    if fix_mode != FixMode::Off {
+        let fixed = fix::fix_content(&content, file_path, &options.config);
+        if fixed != content {
+            if fix_mode == FixMode::Apply {
+                if let Err(e) = fs::write(file_path, &fixed) {
+                    report.diagnostics.push(Diagnostic {
+                        rule: "unreadable-file",
+                        severity: Severity::Error,
+                        message: format!("Failed to write fixes: {}", e),
+                        path: file_path.to_path_buf(),
+                        range: Range::line(1, ""),
+                    });
+                    return Some(report);
+                }
+            }
+            report.fixes.push((
+                file_path.to_path_buf(),
+                fix::unified_diff(&content, &fixed, file_path),
+            ));
+            content = fixed;
+        }
+    }
+
+    let diagnostics = lint_content(&content, file_path, &options.config);
+    let mut diagnostics = rules::apply_rule_config(diagnostics, &content, &options.config);
+    diagnostics.sort_by_key(|d| (d.range.start_line, d.range.start_column));
+    report.diagnostics.extend(diagnostics);
+    Some(report)
+}
+
+/// Validates the content of a single chat file, returning every diagnostic found.
+fn lint_content(content: &str, file_path: &Path, config: &LintConfig) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let lines: Vec<&str> = content.lines().collect();
+
+    let mut push = |rule: &'static str, message: String, range: Range| {
+        diagnostics.push(Diagnostic {
+            rule,
+            severity: Severity::Error,
+            message,
+            path: file_path.to_path_buf(),
+            range,
+        });
+    };
+
+    let Some((start, end)) = find_frontmatter(&lines) else {
+        push(
+            "missing-frontmatter",
+            "Missing frontmatter delimited by '---' lines.".to_string(),
+            Range::line(1, lines.first().copied().unwrap_or_default()),
+        );
+        return diagnostics;
+    };
+
+    frontmatter::validate_frontmatter(&lines, start, end, config, &mut push);
+
+    validate_chat_structure(&lines, end + 1, file_path, &mut push);
+
+    diagnostics
+}
+
+/// Returns the 0-based line indices of the opening and closing frontmatter delimiters.
+///
+/// The opening `---` must be the first line that isn't blank, so horizontal rules
+/// further down a markdown document are not taken for frontmatter.
+pub(crate) fn find_frontmatter(lines: &[&str]) -> Option<(usize, usize)> {
+    let start = lines.iter().position(|line| !line.trim().is_empty())?;
+    if lines[start].trim() != "---" {
+        return None;
+    }
+    let end = (start + 1..lines.len()).find(|&index| lines[index].trim() == "---")?;
+    Some((start, end))
+}
+
+/// Validates the structure of the chat entries that start at line index `body_start`.
+fn validate_chat_structure(
+    lines: &[&str],
+    body_start: usize,
+    file_path: &Path,
+    push: &mut impl FnMut(&'static str, String, Range),
+) {
+    let body: Vec<(usize, &str)> = lines
+        .iter()
+        .copied()
+        .enumerate()
+        .skip(body_start)
+        .filter(|(_, line)| !line.trim().is_empty())
+        .collect();
+
+    let (Some(&(first_index, first_line)), Some(&(last_index, last_line))) =
+        (body.first(), body.last())
+    else {
+        let delimiter = body_start.saturating_sub(1);
+        push(
+            "empty-chat",
+            "Chat structure error: No content after frontmatter.".to_string(),
+            Range::line(
+                delimiter + 1,
+                lines.get(delimiter).copied().unwrap_or_default(),
+            ),
+        );
+        return;
+    };
+
+    let mut code = vec![false; body_start];
+    code.extend(code_lines(&lines[body_start..]));
+
+    validate_role_markers(&body, &code, push);
+
+    if entry_role(first_line) != Some("user") {
+        push(
+            "first-entry-user",
+            "Chat structure error: First entry after frontmatter must start with 'user:'."
+                .to_string(),
+            Range::line(first_index + 1, first_line),
+        );
+    }
+
+    validate_alternating_entries(&body, push);
+    validate_metadata_placement(&body, &code, push);
+
+    if entry_role(last_line) != Some("user") {
+        push(
+            "last-entry-user",
+            "Chat structure error: Last entry must start with 'user:'.".to_string(),
+            Range::line(last_index + 1, last_line),
+        );
+    }
+
+    validate_file_references(&body, file_path, push);
+}
+
+/// Matches a role marker, tolerating stray whitespace around the role name.
+fn role_marker_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"^\s*(user|assistant)\s*:").expect("Failed to compile role marker regex.")
+    })
+}
+
+/// Returns the role introduced by a line, if it starts a new entry.
+///
+/// Like the parser, only `user:` and `assistant:` at the start of a line count.
+pub(crate) fn entry_role(line: &str) -> Option<&'static str> {
+    if line.starts_with("user:") {
+        Some("user")
+    } else if line.starts_with("assistant:") {
+        Some("assistant")
+    } else {
+        None
+    }
+}
+
+/// Returns the role of a line that looks like a role marker but isn't one, such as `  user :`.
+pub(crate) fn near_miss_role(line: &str) -> Option<&'static str> {
+    if entry_role(line).is_some() {
+        return None;
+    }
+    let caps = role_marker_regex().captures(line)?;
+    match &caps[1] {
+        "user" => Some("user"),
+        _ => Some("assistant"),
+    }
+}
+
+/// Returns, for each line, whether it is part of a fenced code block, fences included.
+///
+/// A block left open ends at the next role marker, where the parser starts a new entry.
+pub(crate) fn code_lines<S: AsRef<str>>(lines: &[S]) -> Vec<bool> {
+    let mut fence: Option<(char, usize)> = None;
+    lines
+        .iter()
+        .map(|line| {
+            let line = line.as_ref();
+            if entry_role(line).is_some() {
+                fence = None;
+                return false;
+            }
+            let trimmed = line.trim_start();
+            let marker = trimmed.chars().next().filter(|c| *c == '`' || *c == '~');
+            let run = marker.map_or(0, |marker| {
+                trimmed.chars().take_while(|c| *c == marker).count()
+            });
+            match (fence, marker) {
+                (None, Some(marker)) if run >= 3 => {
+                    fence = Some((marker, run));
+                    true
+                }
+                (Some((open, length)), Some(marker))
+                    if marker == open && run >= length && trimmed[run..].trim().is_empty() =>
+                {
+                    fence = None;
+                    true
+                }
+                (Some(_), _) => true,
+                _ => false,
+            }
+        })
+        .collect()
+}
+
+/// Returns whether a line is one of the `<!-- key: value -->` comments written after an answer.
+pub(crate) fn is_metadata_comment(line: &str) -> bool {
+    let trimmed = line.trim();
+    let Some(inner) = trimmed
+        .strip_prefix("<!--")
+        .and_then(|rest| rest.strip_suffix("-->"))
+    else {
+        return false;
+    };
+    inner
+        .split_once(':')
+        .is_some_and(|(key, _)| METADATA_KEYS.contains(&key.trim()))
+}
+
+/// Reports role markers with leading whitespace or a space before the colon, outside code blocks.
+fn validate_role_markers(
+    body: &[(usize, &str)],
+    code: &[bool],
+    push: &mut impl FnMut(&'static str, String, Range),
+) {
+    for &(index, line) in body {
+        if code[index] {
+            continue;
+        }
+        if let Some(role) = near_miss_role(line) {
+            push(
+                "role-marker",
+                format!(
+                    "Role marker should be written as '{}:' at the start of the line.",
+                    role
+                ),
+                Range::line(index + 1, line),
+            );
+        }
+    }
+}
+
+/// Reports metadata comments inside user entries or followed by more assistant content.
+fn validate_metadata_placement(
+    body: &[(usize, &str)],
+    code: &[bool],
+    push: &mut impl FnMut(&'static str, String, Range),
+) {
+    let mut role = None;
+
+    for (position, &(index, line)) in body.iter().enumerate() {
+        if let Some(next_role) = entry_role(line) {
+            role = Some(next_role);
+            continue;
+        }
+        if code[index] || !is_metadata_comment(line) {
+            continue;
+        }
+
+        let misplaced = match role {
+            Some("assistant") => body[position + 1..]
+                .iter()
+                .take_while(|(_, next)| entry_role(next).is_none())
+                .any(|&(next_index, next)| code[next_index] || !is_metadata_comment(next)),
+            _ => true,
+        };
+        if misplaced {
+            push(
+                "metadata-placement",
+                "Metadata comment must come after the content of an assistant entry.".to_string(),
+                Range::line(index + 1, line),
+            );
+        }
+    }
+}
+
+/// Ensures that entries alternate between 'user:' and 'assistant:'.
+fn validate_alternating_entries(
+    body: &[(usize, &str)],
+    push: &mut impl FnMut(&'static str, String, Range),
+) {
+    let mut previous: Option<&str> = None;
+
+    for &(index, line) in body {
+        let Some(role) = entry_role(line) else {
+            continue;
+        };
+
+        if previous == Some(role) {
+            push(
+                "alternating-entries",
+                format!(
+                    "Chat structure error: Entries must alternate between 'user:' and 'assistant:', found two '{}:' entries in a row.",
+                    role
+                ),
+                Range::line(index + 1, line),
+            );
+        }
+        previous = Some(role);
+    }
+}
+
+/// Reports file references within user entries that do not exist on disk.
+fn validate_file_references(
+    body: &[(usize, &str)],
+    current_file_path: &Path,
+    push: &mut impl FnMut(&'static str, String, Range),
+) {
+    let file_pattern =
+        Regex::new(r"\[\[([^\]\n]+)\]\]").expect("Failed to compile file reference regex.");
+    let mut in_user_entry = false;
+
+    for &(index, line) in body {
+        if let Some(role) = entry_role(line) {
+            in_user_entry = role == "user";
+        }
+        if !in_user_entry {
+            continue;
+        }
+
+        for cap in file_pattern.captures_iter(line) {
+            let whole = cap.get(0).unwrap();
+            let referenced_path = &cap[1];
+            let resolved_path = resolve_referenced_path(referenced_path, current_file_path);
+            if !resolved_path.exists() {
+                let start_column = line[..whole.start()].chars().count() + 1;
+                push(
+                    "file-reference",
+                    format!("File reference error: '{}' not found.", referenced_path),
+                    Range {
+                        start_line: index + 1,
+                        start_column,
+                        end_line: index + 1,
+                        end_column: start_column + whole.as_str().chars().count(),
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// Resolves the full path of a referenced file based on the current file's path.
+fn resolve_referenced_path(file_path: &str, current_file_path: &Path) -> PathBuf {
+    let sanitized_path = file_path.replace(r"\ ", " "); // Handle escaped spaces
+    let path = Path::new(&sanitized_path);
+
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        current_file_path
+            .parent()
+            .map_or(path.to_path_buf(), |parent| parent.join(path))
+    }
+}
+
+/// Validates that the provided path exists and is accessible.
+pub fn is_valid_path(path: &str) -> bool {
+    Path::new(path).exists()
+}
+
+/// Defines the lint command for the CLI application.
+pub fn lint_command() -> Command {
+    Command::new("lint")
+        .about("Lint a chat file or directory")
+        .arg(
+            Arg::new("path")
+                .help("Path to the file or directory")
+                .required_unless_present("list-rules")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("list-rules")
+                .long("list-rules")
+                .help("List every lint rule with its default severity")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .help("Output format for diagnostics")
+                .value_parser(["text", "json", "sarif"])
+                .default_value("text")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("include")
+                .long("include")
+                .help("Only lint files matching this glob (repeatable)")
+                .action(clap::ArgAction::Append)
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("exclude")
+                .long("exclude")
+                .help("Skip files and directories matching this glob (repeatable)")
+                .action(clap::ArgAction::Append)
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("fix")
+                .long("fix")
+                .help("Apply safe fixes in place and report what remains")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .help("With --fix, print a unified diff instead of writing files")
+                .requires("fix")
+                .action(clap::ArgAction::SetTrue),
+        )
+}
@@ -0,0 +1,126 @@
+use serde_json::{json, Value};
+
+use super::{Diagnostic, LintReport, Range, Severity, RULES};
+
+/// Version of the `--format json` schema. Bump it on any incompatible change.
+const JSON_SCHEMA_VERSION: u32 = 1;
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// Prints diagnostics as `path:line:column: severity [rule] message`, one per line.
+pub fn print_text(report: &LintReport) {
+    for file in &report.files {
+        let mut clean = true;
+        for diagnostic in report.diagnostics.iter().filter(|d| &d.path == file) {
+            clean = false;
+            eprintln!(
+                "{}:{}:{}: {} [{}] {}",
+                diagnostic.path.display(),
+                diagnostic.range.start_line,
+                diagnostic.range.start_column,
+                diagnostic.severity.as_str(),
+                diagnostic.rule,
+                diagnostic.message
+            );
+        }
+        if clean {
+            println!("{} is valid.", file.display());
+        }
+    }
+}
+
+/// Renders the report as a versioned JSON document.
+pub fn to_json(report: &LintReport) -> String {
+    let document = json!({
+        "version": JSON_SCHEMA_VERSION,
+        "files": report.files.iter().map(|file| file.display().to_string()).collect::<Vec<_>>(),
+        "diagnostics": report.diagnostics.iter().map(diagnostic_to_json).collect::<Vec<_>>(),
+    });
+    serde_json::to_string_pretty(&document).expect("Lint report is always serializable")
+}
+
+fn diagnostic_to_json(diagnostic: &Diagnostic) -> Value {
+    json!({
+        "rule": diagnostic.rule,
+        "severity": diagnostic.severity.as_str(),
+        "message": diagnostic.message,
+        "path": diagnostic.path.display().to_string(),
+        "range": range_to_json(&diagnostic.range),
+    })
+}
+
+fn range_to_json(range: &Range) -> Value {
+    json!({
+        "start": { "line": range.start_line, "column": range.start_column },
+        "end": { "line": range.end_line, "column": range.end_column },
+    })
+}
+
+/// Renders the report as a SARIF 2.1.0 log for code-scanning tools.
+pub fn to_sarif(report: &LintReport) -> String {
+    let rules: Vec<Value> = RULES
+        .iter()
+        .map(|rule| {
+            json!({
+                "id": rule.id,
+                "shortDescription": { "text": rule.description },
+                "defaultConfiguration": { "level": sarif_level(rule.default_severity) },
+            })
+        })
+        .collect();
+
+    let results: Vec<Value> = report
+        .diagnostics
+        .iter()
+        .map(|diagnostic| {
+            json!({
+                "ruleId": diagnostic.rule,
+                "ruleIndex": RULES.iter().position(|rule| rule.id == diagnostic.rule),
+                "level": sarif_level(diagnostic.severity),
+                "message": { "text": diagnostic.message },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": { "uri": artifact_uri(diagnostic) },
+                        "region": {
+                            "startLine": diagnostic.range.start_line,
+                            "startColumn": diagnostic.range.start_column,
+                            "endLine": diagnostic.range.end_line,
+                            "endColumn": diagnostic.range.end_column,
+                        },
+                    },
+                }],
+            })
+        })
+        .collect();
+
+    let document = json!({
+        "$schema": SARIF_SCHEMA,
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": env!("CARGO_PKG_REPOSITORY"),
+                    "rules": rules,
+                },
+            },
+            "columnKind": "unicodeCodePoints",
+            "results": results,
+        }],
+    });
+    serde_json::to_string_pretty(&document).expect("SARIF log is always serializable")
+}
+
+fn sarif_level(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Off => "none",
+    }
+}
+
+/// SARIF wants forward-slash URIs, even for relative Windows paths.
+fn artifact_uri(diagnostic: &Diagnostic) -> String {
+    diagnostic.path.display().to_string().replace('\\', "/")
+}
@@ -0,0 +1,247 @@
+use serde::{Deserialize, Serialize};
+
+use super::Diagnostic;
+use crate::chat::config::LintConfig;
+
+/// How a rule's diagnostics are treated. Only errors make `chat lint` fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Off,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Off => "off",
+        }
+    }
+}
+
+/// A lint rule that diagnostics can refer to by id.
+pub struct Rule {
+    pub id: &'static str,
+    pub description: &'static str,
+    pub default_severity: Severity,
+}
+
+/// Every rule `chat lint` knows about, in the order they are checked.
+pub const RULES: &[Rule] = &[
+    Rule {
+        id: "unreadable-file",
+        description: "The file must be readable as UTF-8 text.",
+        default_severity: Severity::Error,
+    },
+    Rule {
+        id: "missing-frontmatter",
+        description: "The file must start with a '---' delimited frontmatter block.",
+        default_severity: Severity::Error,
+    },
+    Rule {
+        id: "frontmatter-key",
+        description: "Every key from the frontmatter template must be present.",
+        default_severity: Severity::Error,
+    },
+    Rule {
+        id: "frontmatter-empty-value",
+        description: "Frontmatter keys must have a value, except those listed in lint.allow_empty.",
+        default_severity: Severity::Error,
+    },
+    Rule {
+        id: "frontmatter-unknown-key",
+        description: "Frontmatter keys should be ones samvada understands.",
+        default_severity: Severity::Warning,
+    },
+    Rule {
+        id: "frontmatter-timestamp",
+        description: "'created_at' and 'updated_at' must be RFC 3339 timestamps.",
+        default_severity: Severity::Error,
+    },
+    Rule {
+        id: "frontmatter-timestamp-order",
+        description: "'updated_at' must not be earlier than 'created_at'.",
+        default_severity: Severity::Error,
+    },
+    Rule {
+        id: "frontmatter-endpoint",
+        description: "'api_endpoint' must be a valid http or https URL.",
+        default_severity: Severity::Error,
+    },
+    Rule {
+        id: "frontmatter-tags",
+        description: "'tags' must be a list.",
+        default_severity: Severity::Error,
+    },
+    Rule {
+        id: "frontmatter-params",
+        description: "'params' must be a JSON object of extra request parameters.",
+        default_severity: Severity::Error,
+    },
+    Rule {
+        id: "frontmatter-private",
+        description: "'private' must be true or false.",
+        default_severity: Severity::Error,
+    },
+    Rule {
+        id: "unknown-model",
+        description: "'model' should be one of lint.known_models.",
+        default_severity: Severity::Warning,
+    },
+    Rule {
+        id: "empty-chat",
+        description: "There must be at least one entry after the frontmatter.",
+        default_severity: Severity::Error,
+    },
+    Rule {
+        id: "role-marker",
+        description:
+            "Role markers must be written as 'user:' or 'assistant:' at the start of a line.",
+        default_severity: Severity::Error,
+    },
+    Rule {
+        id: "first-entry-user",
+        description: "The first entry after the frontmatter must start with 'user:'.",
+        default_severity: Severity::Error,
+    },
+    Rule {
+        id: "alternating-entries",
+        description: "Entries must alternate between 'user:' and 'assistant:'.",
+        default_severity: Severity::Error,
+    },
+    Rule {
+        id: "last-entry-user",
+        description: "The last entry must start with 'user:'.",
+        default_severity: Severity::Error,
+    },
+    Rule {
+        id: "metadata-placement",
+        description: "Response metadata comments must follow the content of an assistant entry.",
+        default_severity: Severity::Error,
+    },
+    Rule {
+        id: "file-reference",
+        description: "Every [[file]] reference in a user entry must point to an existing file.",
+        default_severity: Severity::Error,
+    },
+];
+
+const DISABLE_DIRECTIVE: &str = "samvada-lint-disable";
+const DISABLE_NEXT_LINE_DIRECTIVE: &str = "samvada-lint-disable-next-line";
+
+/// Returns the configured severity of a rule, falling back to its default.
+pub fn severity_for(rule_id: &str, config: &LintConfig) -> Severity {
+    config.rules.get(rule_id).copied().unwrap_or_else(|| {
+        RULES
+            .iter()
+            .find(|rule| rule.id == rule_id)
+            .map_or(Severity::Error, |rule| rule.default_severity)
+    })
+}
+
+/// Returns the rule ids in `config` that don't name a known rule.
+pub fn unknown_rules(config: &LintConfig) -> Vec<&str> {
+    let mut unknown: Vec<&str> = config
+        .rules
+        .keys()
+        .map(String::as_str)
+        .filter(|id| !RULES.iter().any(|rule| rule.id == *id))
+        .collect();
+    unknown.sort();
+    unknown
+}
+
+/// Returns whether a line is a `samvada-lint-disable` comment.
+pub fn is_directive_comment(line: &str) -> bool {
+    parse_directive(line).is_some()
+}
+
+/// Returns whether a problem of `rule` on `line` (1-based) is reported, so may be fixed.
+pub fn reports(rule: &str, line: usize, config: &LintConfig, suppressions: &Suppressions) -> bool {
+    severity_for(rule, config) != Severity::Off && !suppressions.covers(rule, line)
+}
+
+/// Sets each diagnostic's configured severity and drops those that are off
+/// or suppressed by a `samvada-lint-disable` comment in `content`.
+pub fn apply_rule_config(
+    diagnostics: Vec<Diagnostic>,
+    content: &str,
+    config: &LintConfig,
+) -> Vec<Diagnostic> {
+    let suppressions = Suppressions::parse(content);
+
+    diagnostics
+        .into_iter()
+        .filter_map(|mut diagnostic| {
+            diagnostic.severity = severity_for(diagnostic.rule, config);
+            let suppressed = suppressions.covers(diagnostic.rule, diagnostic.range.start_line);
+            (diagnostic.severity != Severity::Off && !suppressed).then_some(diagnostic)
+        })
+        .collect()
+}
+
+/// Rules named in a directive; an empty list means every rule.
+type RuleList = Vec<String>;
+
+/// Inline suppressions found in a file.
+///
+/// `<!-- samvada-lint-disable rule-a, rule-b -->` disables rules for the whole
+/// file and `<!-- samvada-lint-disable-next-line rule -->` for the next line only.
+/// Without rule ids, every rule is disabled.
+#[derive(Default)]
+pub struct Suppressions {
+    file: Vec<RuleList>,
+    lines: Vec<(usize, RuleList)>,
+}
+
+impl Suppressions {
+    pub fn parse(content: &str) -> Self {
+        let mut suppressions = Suppressions::default();
+        for (index, line) in content.lines().enumerate() {
+            match parse_directive(line) {
+                Some((true, rules)) => suppressions.lines.push((index + 2, rules)),
+                Some((false, rules)) => suppressions.file.push(rules),
+                None => {}
+            }
+        }
+        suppressions
+    }
+
+    pub fn covers(&self, rule: &str, line: usize) -> bool {
+        let names = |rules: &RuleList| rules.is_empty() || rules.iter().any(|id| id == rule);
+
+        self.file.iter().any(names)
+            || self
+                .lines
+                .iter()
+                .any(|(target, rules)| *target == line && names(rules))
+    }
+}
+
+/// Parses a directive comment into (applies to next line only, rule ids).
+fn parse_directive(line: &str) -> Option<(bool, RuleList)> {
+    let inner = line
+        .trim()
+        .strip_prefix("<!--")?
+        .strip_suffix("-->")?
+        .trim();
+
+    let (next_line, rest) = if let Some(rest) = inner.strip_prefix(DISABLE_NEXT_LINE_DIRECTIVE) {
+        (true, rest)
+    } else {
+        (false, inner.strip_prefix(DISABLE_DIRECTIVE)?)
+    };
+    if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let rules = rest
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|id| !id.is_empty())
+        .map(str::to_string)
+        .collect();
+    Some((next_line, rules))
+}
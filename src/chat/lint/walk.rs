@@ -0,0 +1,83 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
+use std::path::{Path, PathBuf};
+
+use super::find_frontmatter;
+
+/// Name of the per-directory ignore file, using `.gitignore` syntax.
+pub const IGNORE_FILE_NAME: &str = ".samvadaignore";
+
+/// Frontmatter keys that only samvada chat files have, as opposed to other markdown.
+const CHAT_FRONTMATTER_KEYS: &[&str] = &["system", "model", "api_endpoint"];
+
+/// Recursively collects the `*.md` files under `root` that are not ignored.
+///
+/// `.gitignore` and `.samvadaignore` files are honoured, even outside a git
+/// repository. `include` and `exclude` globs are matched against paths relative
+/// to `root`; an excluded directory is not descended into.
+pub fn collect_markdown_files(
+    root: &Path,
+    include: &[String],
+    exclude: &[String],
+) -> Result<Vec<PathBuf>, String> {
+    let include = build_glob_set(include)?;
+    let exclude = build_glob_set(exclude)?;
+
+    let walk_root = root.to_path_buf();
+    let exclude_filter = exclude.clone();
+    let walker = WalkBuilder::new(root)
+        .require_git(false)
+        .add_custom_ignore_filename(IGNORE_FILE_NAME)
+        .filter_entry(move |entry| {
+            let relative = entry
+                .path()
+                .strip_prefix(&walk_root)
+                .unwrap_or(entry.path());
+            relative.as_os_str().is_empty() || !exclude_filter.is_match(relative)
+        })
+        .build();
+
+    let mut files = Vec::new();
+    for entry in walker {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let path = entry.path();
+        if !entry.file_type().is_some_and(|kind| kind.is_file()) || !is_markdown(path) {
+            continue;
+        }
+
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        if !include.is_empty() && !include.is_match(relative) {
+            continue;
+        }
+        files.push(path.to_path_buf());
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Returns whether the content starts with frontmatter that samvada wrote or understands.
+pub fn is_chat_file(content: &str) -> bool {
+    let lines: Vec<&str> = content.lines().collect();
+    let Some((start, end)) = find_frontmatter(&lines) else {
+        return false;
+    };
+
+    lines[start + 1..end].iter().any(|line| {
+        line.split_once(':')
+            .is_some_and(|(key, _)| CHAT_FRONTMATTER_KEYS.contains(&key.trim()))
+    })
+}
+
+fn is_markdown(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "md")
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet, String> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern).map_err(|e| format!("Invalid glob '{}': {}", pattern, e))?;
+        builder.add(glob);
+    }
+    builder.build().map_err(|e| e.to_string())
+}
@@ -1,23 +1,70 @@
 use chrono::Local;
+use dirs::home_dir;
 use log::debug;
 use simplelog::{
-    CombinedLogger, ConfigBuilder, LevelFilter, LevelPadding, ThreadLogMode, WriteLogger,
+    CombinedLogger, ConfigBuilder, LevelFilter, LevelPadding, SharedLogger, ThreadLogMode,
+    WriteLogger,
 };
-use std::fs::File;
+use std::fs::{self, OpenOptions};
+use std::io;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
 use time::macros::format_description;
 use time::UtcOffset;
 
+use crate::chat::config::{load_config_for, AppConfig};
+use crate::chat::request_log;
+use crate::error::{Error, Result};
+
+/// Logging choices made with the global `-v`, `-q`, `--log-file` and `--log-stderr` flags.
+#[derive(Debug, Default)]
+pub struct LogFlags {
+    /// Number of `-v` flags, or -1 for `-q`.
+    pub verbosity: i8,
+    pub log_file: Option<PathBuf>,
+    pub stderr: bool,
+}
+
+static LOG_FLAGS: OnceLock<LogFlags> = OnceLock::new();
+
+/// Set for chats with `private: true`, whose messages must not be logged.
+static PRIVATE: AtomicBool = AtomicBool::new(false);
+
+/// Records the logging flags given on the command line.
+pub fn set_log_flags(flags: LogFlags) {
+    let _ = LOG_FLAGS.set(flags);
+}
+
+/// Stops message content from being logged, for chats with `private: true`.
+pub fn set_private(private: bool) {
+    PRIVATE.store(private, Ordering::Relaxed);
+}
+
+/// Whether prompts and answers may be written to the logs.
+pub fn log_content() -> bool {
+    !PRIVATE.load(Ordering::Relaxed)
+}
+
 /// Sets up logging configuration based on the provided file path or defaults to "samvada.log".
-pub fn setup_logging(file_path: Option<&str>) -> PathBuf {
-    let log_path = if let Some(file_path) = file_path {
-        let path = Path::new(file_path);
-        let stem = path.file_stem().unwrap_or_default();
-        let parent = path.parent().unwrap_or_else(|| Path::new(""));
-        parent.join(format!("{}.log", stem.to_str().unwrap()))
-    } else {
-        // Default log file name if no file_path is provided
-        PathBuf::from("samvada.log")
+///
+/// The text log and the request log (`<chat>.requests.jsonl`) are appended to, next to
+/// the chat or in `log_dir`. Returns the text log's path, or `None` with `logging: off`.
+pub fn setup_logging(file_path: Option<&str>) -> Result<Option<PathBuf>> {
+    let default_flags = LogFlags::default();
+    let flags = LOG_FLAGS.get().unwrap_or(&default_flags);
+
+    // Settings come from the project config of the chat, if it has one
+    let start = file_path
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("."));
+    let app_config = load_config_for(&start).ok();
+
+    let level = match flags.verbosity {
+        i8::MIN..=-1 => LevelFilter::Warn,
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
     };
 
     let offset_in_sec = Local::now().offset().local_minus_utc();
@@ -27,8 +74,6 @@ pub fn setup_logging(file_path: Option<&str>) -> PathBuf {
         UtcOffset::UTC
     });
 
-    debug!("Using UTC offset: {:?}", local_offset);
-
     let config = ConfigBuilder::new()
         .set_thread_mode(ThreadLogMode::Both)
         .set_level_padding(LevelPadding::Right)
@@ -38,12 +83,87 @@ pub fn setup_logging(file_path: Option<&str>) -> PathBuf {
         .set_time_offset(local_offset)
         .build();
 
-    CombinedLogger::init(vec![WriteLogger::new(
-        LevelFilter::Debug,
-        config,
-        File::create(&log_path).expect("Failed to create log file"),
-    )])
-    .expect("Failed to initialize loggers");
+    let mut loggers: Vec<Box<dyn SharedLogger>> = Vec::new();
+    if flags.stderr || app_config.as_ref().is_some_and(|app| app.log_stderr) {
+        loggers.push(WriteLogger::new(level, config.clone(), io::stderr()));
+    }
+
+    // A read-only directory shouldn't stop the stderr mirror, so the error is returned last
+    let mut log_path = None;
+    let mut open_error = None;
+    if let Some((text_log, requests_log)) = log_paths(file_path, flags, app_config.as_ref()) {
+        let request_config = app_config.map(|app| app.request_log).unwrap_or_default();
+        request_log::init(requests_log, request_config);
+
+        match open_log(&text_log) {
+            Ok(log_file) => {
+                loggers.push(WriteLogger::new(level, config, log_file));
+                log_path = Some(text_log);
+            }
+            Err(e) => {
+                open_error = Some(Error::io(
+                    format!("Failed to open log file {}", text_log.display()),
+                    e,
+                ))
+            }
+        }
+    }
+
+    CombinedLogger::init(loggers)
+        .map_err(|e| Error::io("Failed to initialize logging", io::Error::other(e)))?;
+    debug!("Using UTC offset: {:?}", local_offset);
+
+    match open_error {
+        Some(e) => Err(e),
+        None => Ok(log_path),
+    }
+}
+
+/// Returns the paths of the text log and the request log, or `None` with `logging: off`.
+///
+/// `--log-file` wins over `logging: off` and `log_dir`.
+fn log_paths(
+    file_path: Option<&str>,
+    flags: &LogFlags,
+    app_config: Option<&AppConfig>,
+) -> Option<(PathBuf, PathBuf)> {
+    if let Some(log_file) = &flags.log_file {
+        return Some((log_file.clone(), log_file.with_extension("requests.jsonl")));
+    }
+    if app_config.is_some_and(|app| !app.logging) {
+        return None;
+    }
+
+    let (parent, stem) = if let Some(file_path) = file_path {
+        let path = Path::new(file_path);
+        let stem = path.file_stem().unwrap_or_default();
+        let parent = path.parent().unwrap_or_else(|| Path::new(""));
+        (parent.to_path_buf(), stem.to_string_lossy().into_owned())
+    } else {
+        // Default log file name if no file_path is provided
+        (PathBuf::new(), "samvada".to_string())
+    };
+    let dir = match app_config.and_then(|app| app.log_dir.as_deref()) {
+        Some(log_dir) => expand_home(log_dir),
+        None => parent,
+    };
+    Some((
+        dir.join(format!("{}.log", stem)),
+        dir.join(format!("{}.requests.jsonl", stem)),
+    ))
+}
+
+fn open_log(path: &Path) -> io::Result<fs::File> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    OpenOptions::new().append(true).create(true).open(path)
+}
 
-    log_path
+/// Expands a leading `~/` to the home directory.
+pub(crate) fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
 }
@@ -1,34 +1,43 @@
 pub mod ask;
 pub mod constants;
 pub mod create;
+pub mod fmt;
 pub mod lint;
 pub mod quick;
+pub mod repl;
+pub mod templates;
 
 // Internal shared modules
+pub(crate) mod _utils;
 mod api;
-mod config;
-mod logging;
-mod parser;
-mod _utils;
+pub(crate) mod budget;
+pub(crate) mod cache;
+pub(crate) mod cassette;
+pub(crate) mod config;
+pub(crate) mod credentials;
+pub(crate) mod dry_run;
+pub(crate) mod logging;
+pub(crate) mod parser;
+pub(crate) mod personas;
+mod request_log;
+pub(crate) mod usage;
+pub(crate) mod vault;
 
 use clap::ArgMatches;
 
-pub async fn handle_chat_subcommand(matches: &ArgMatches) {
+pub async fn handle_chat_subcommand(matches: &ArgMatches) -> crate::error::Result<()> {
     match matches.subcommand() {
-        Some(("create", new_m)) => {
-            create::handle_create_subcommand(new_m);
+        Some(("create", new_m)) => create::handle_create_subcommand(new_m),
+        Some(("lint", lint_m)) => lint::handle_lint_subcommand(lint_m),
+        Some(("fmt", fmt_m)) => fmt::handle_fmt_subcommand(fmt_m),
+        Some(("ask", ask_m)) => ask::handle_ask_subcommand(ask_m).await,
+        Some(("templates", templates_m)) => templates::handle_templates_subcommand(templates_m),
+        Some(("quick", quick_m)) => quick::handle_quick_subcommand(quick_m).await,
+        Some(("repl", repl_m)) => repl::handle_repl_subcommand(repl_m).await,
+        _ => {
+            println!("No valid chat subcommand was used");
+            Ok(())
         }
-        Some(("lint", lint_m)) => {
-            lint::handle_lint_subcommand(lint_m);
-        }
-        Some(("ask", ask_m)) => {
-            ask::handle_ask_subcommand(ask_m).await;
-        }
-        Some(("quick", quick_m)) => {
-            // Add this block
-            quick::handle_quick_subcommand(quick_m).await;
-        }
-        _ => println!("No valid chat subcommand was used"),
     }
 }
 
@@ -37,6 +46,9 @@ pub fn chat_command() -> clap::Command {
         .about("Manage chat files")
         .subcommand(create::create_command())
         .subcommand(lint::lint_command())
+        .subcommand(fmt::fmt_command())
         .subcommand(ask::ask_command())
-        .subcommand(quick::quick_command()) // Add this line
+        .subcommand(quick::quick_command())
+        .subcommand(repl::repl_command())
+        .subcommand(templates::templates_command())
 }
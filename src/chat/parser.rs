@@ -1,48 +1,299 @@
-use crate::chat::config::load_config;
+use crate::chat::_utils::ResponseMetadata;
+use crate::chat::config::{load_config_for, profile_flag, AppConfig};
+use crate::chat::lint::rules::is_directive_comment;
+use crate::chat::lint::{entry_role, is_metadata_comment};
+use crate::chat::personas::find_persona;
+use crate::error::{Error, Result};
 use log::{debug, info};
-use serde_json::{json, Value};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
 use std::fs;
 use std::fs::File;
-use std::io::{self, BufRead, BufReader, Error as IoError};
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// Everything needed to send a chat file to the API.
+#[derive(Debug, Clone)]
+pub struct ParsedChat {
+    pub system_prompt: String,
+    pub model: String,
+    pub api_endpoint: String,
+    /// Extra request parameters such as `temperature`, from the `params` key.
+    pub params: Map<String, Value>,
+    /// Name of the persona the chat uses, if any.
+    pub persona: Option<String>,
+    /// Name of the active profile, if any.
+    pub profile: Option<String>,
+    /// Variable holding the API key, when the active profile names one.
+    pub api_key_env: Option<String>,
+    /// Saved credential to use, when the active profile names one.
+    pub credential: Option<String>,
+    /// Values of the `tags` key.
+    pub tags: Vec<String>,
+    /// Set with `private: true`, so the messages are kept out of the logs.
+    pub private: bool,
+    /// `(role, content)` pairs in file order.
+    pub messages: Vec<(String, String)>,
+}
 
 /// Parses a file to extract system prompt, model, and messages.
-pub fn parse_file(
-    file_path: &str,
-) -> Result<(String, String, String, Vec<(String, String)>), std::io::Error> {
+pub fn parse_file(file_path: &str) -> Result<ParsedChat> {
     info!("Parsing file: {}", file_path);
-    let file = File::open(file_path)?;
+    let read_error = |e| Error::io(format!("Failed to read {}", file_path), e);
+    let file = File::open(file_path).map_err(read_error)?;
     let reader = BufReader::new(file);
     let mut lines = reader.lines();
 
-    let (system_prompt, model, api_endpoint) = parse_frontmatter(&mut lines)?;
-    let messages = parse_messages(&mut lines)?;
+    // Load defaults from the config that applies to the chat's directory
+    let config = load_config_for(Path::new(file_path))?;
+
+    // Say which file a parse or read error comes from
+    let in_file = |e| match e {
+        Error::Parse(message) => Error::Parse(format!("{}: {}", file_path, message)),
+        Error::Io { source, .. } => read_error(source),
+        e => e,
+    };
+    let mut chat = parse_frontmatter(&mut lines, config).map_err(in_file)?;
+    chat.messages = parse_messages(&mut lines).map_err(in_file)?;
+
+    if !chat.private {
+        debug!("Parsed system prompt: {}", chat.system_prompt);
+    }
+    debug!("Using model: {}", chat.model);
+    debug!("Using API endpoint: {}", chat.api_endpoint);
+    debug!("Parsed {} messages", chat.messages.len());
+
+    Ok(chat)
+}
+
+/// Builds the settings for a chat without a file: the config defaults with the
+/// active profile (`--profile`, else the config's `profile`) applied.
+pub fn chat_from_config(config: &AppConfig) -> Result<ParsedChat> {
+    let mut chat = ParsedChat {
+        system_prompt: config.system_prompt.clone(),
+        model: config.model.clone(),
+        api_endpoint: config.api_endpoint.clone(),
+        params: Map::new(),
+        persona: None,
+        profile: None,
+        api_key_env: None,
+        credential: None,
+        tags: Vec::new(),
+        private: false,
+        messages: Vec::new(),
+    };
+
+    if let Some(name) = profile_flag().or(config.profile.as_deref()) {
+        config
+            .find_profile(name)
+            .map_err(Error::Config)?
+            .apply(&mut chat);
+        chat.profile = Some(name.to_string());
+    }
+    Ok(chat)
+}
+
+/// Parses a `params` frontmatter value, a JSON object such as `{"temperature": 0.2}`.
+pub fn parse_params(value: &str) -> std::result::Result<Map<String, Value>, String> {
+    match serde_json::from_str(value) {
+        Ok(Value::Object(params)) => Ok(params),
+        Ok(_) => Err("params must be a JSON object".to_string()),
+        Err(e) => Err(format!("params is not valid JSON: {}", e)),
+    }
+}
+
+/// Parses a `tags` frontmatter value, written as `[rust, review]` or as a `- rust` block list.
+pub fn parse_tags(value: &str) -> Vec<String> {
+    let value = value.trim();
+    let items: Vec<&str> = match value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+        Some(flow) => flow.split(',').collect(),
+        None => value
+            .lines()
+            .map(|line| line.trim().trim_start_matches('-'))
+            .collect(),
+    };
+    items
+        .into_iter()
+        .map(|tag| tag.trim().trim_matches(['"', '\'']).to_string())
+        .filter(|tag| !tag.is_empty())
+        .collect()
+}
+
+/// Reads the `<!-- key: value -->` comments after each answer, one map per answer.
+pub fn parse_metadata_comments(content: &str) -> Vec<BTreeMap<String, String>> {
+    let mut blocks = Vec::new();
+    let mut current = BTreeMap::new();
+    for line in content.lines() {
+        if is_metadata_comment(line) {
+            let inner = line
+                .trim()
+                .trim_start_matches("<!--")
+                .trim_end_matches("-->");
+            if let Some((key, value)) = inner.split_once(':') {
+                current.insert(key.trim().to_string(), value.trim().to_string());
+            }
+        } else if !line.trim().is_empty() && !current.is_empty() {
+            blocks.push(std::mem::take(&mut current));
+        }
+    }
+    if !current.is_empty() {
+        blocks.push(current);
+    }
+    blocks
+}
 
-    debug!("Parsed system prompt: {}", system_prompt);
-    debug!("Using model: {}", model);
-    debug!("Using API endpoint: {}", api_endpoint);
-    debug!("Parsed {} messages", messages.len());
+/// Reads the metadata comments after each answer into structured metadata, one per answer.
+pub fn parse_response_metadata(content: &str) -> Vec<ResponseMetadata> {
+    parse_metadata_comments(content)
+        .iter()
+        .map(ResponseMetadata::from_comments)
+        .collect()
+}
 
-    Ok((system_prompt, model, api_endpoint, messages))
+/// A `key: value` pair from a chat file's frontmatter.
+#[derive(Debug, Clone)]
+pub struct FrontmatterEntry {
+    pub key: String,
+    /// The value, with continuation lines joined by newlines and trimmed.
+    pub value: String,
+    /// 0-based index of the line the key is on.
+    pub line: usize,
 }
 
-/// Parses the frontmatter section of the file to extract system prompt, model, and API endpoint.
+/// Splits a frontmatter line into `(key, value)` if it starts a new key.
+///
+/// Indented lines and lines without a `key:` prefix continue the previous value.
+pub fn split_frontmatter_key(line: &str) -> Option<(&str, &str)> {
+    if line.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let (key, value) = line.split_once(':')?;
+    let is_key = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+    is_key.then_some((key, value))
+}
+
+/// Parses the lines between the frontmatter delimiters into entries.
+///
+/// `first_line` is the 0-based index of `lines[0]` within the whole file.
+pub fn parse_frontmatter_entries(lines: &[&str], first_line: usize) -> Vec<FrontmatterEntry> {
+    let mut entries: Vec<FrontmatterEntry> = Vec::new();
+
+    for (offset, line) in lines.iter().enumerate() {
+        if let Some((key, value)) = split_frontmatter_key(line) {
+            entries.push(FrontmatterEntry {
+                key: key.to_string(),
+                value: value.trim().to_string(),
+                line: first_line + offset,
+            });
+        } else if let Some(entry) = entries.last_mut() {
+            // Continue accumulating the value for the current key
+            entry.value.push('\n');
+            entry.value.push_str(line.trim());
+        }
+    }
+
+    for entry in &mut entries {
+        entry.value = entry.value.trim().to_string();
+    }
+    entries
+}
+
+/// Parses the frontmatter section of the file to extract system prompt, model, API endpoint and params.
+///
+/// The active profile (`profile:` key, else the config's default) applies first,
+/// then a `persona:` key; other keys then override both, except `system` and
+/// `model` left empty. A profile chosen with `--profile` applies last of all.
 fn parse_frontmatter<B: BufRead>(
     lines: &mut std::io::Lines<B>,
-) -> Result<(String, String, String), io::Error> {
-    // Load defaults from config
-    let config = load_config().map_err(|e| {
-        io::Error::new(
-            io::ErrorKind::Other,
-            format!("Failed to load config: {}", e),
-        )
-    })?;
-
-    let mut system_prompt = String::new();
-    let mut model = config.model;
-    let mut api_endpoint = config.api_endpoint;
+    config: AppConfig,
+) -> Result<ParsedChat> {
+    let mut chat = ParsedChat {
+        system_prompt: config.system_prompt.clone(),
+        model: config.model.clone(),
+        api_endpoint: config.api_endpoint.clone(),
+        params: Map::new(),
+        persona: None,
+        profile: None,
+        api_key_env: None,
+        credential: None,
+        tags: Vec::new(),
+        private: false,
+        messages: Vec::new(),
+    };
+
+    let block = read_frontmatter_block(lines)?;
+    let block: Vec<&str> = block.iter().map(String::as_str).collect();
+    let entries = parse_frontmatter_entries(&block, 0);
+
+    let frontmatter_profile = entries
+        .iter()
+        .find(|entry| entry.key == "profile" && !entry.value.is_empty())
+        .map(|entry| entry.value.clone());
+    if profile_flag().is_none() {
+        if let Some(name) = frontmatter_profile.or_else(|| config.profile.clone()) {
+            config
+                .find_profile(&name)
+                .map_err(Error::Config)?
+                .apply(&mut chat);
+            debug!("Using profile: {}", name);
+            chat.profile = Some(name);
+        }
+    }
+
+    if let Some(entry) = entries
+        .iter()
+        .find(|entry| entry.key == "persona" && !entry.value.is_empty())
+    {
+        let persona = find_persona(&entry.value, &config.personas).map_err(Error::Config)?;
+        persona.apply(&mut chat);
+        chat.persona = Some(entry.value.clone());
+        debug!("Using persona: {}", entry.value);
+    }
+
+    for entry in entries {
+        let keep_persona = chat.persona.is_some() && entry.value.is_empty();
+        match entry.key.as_str() {
+            "system" if !keep_persona => chat.system_prompt = entry.value,
+            "model" if !keep_persona => chat.model = entry.value,
+            "api_endpoint" => chat.api_endpoint = entry.value,
+            "private" => chat.private = entry.value == "true",
+            "tags" => chat.tags = parse_tags(&entry.value),
+            "params" if !entry.value.is_empty() => {
+                chat.params
+                    .extend(parse_params(&entry.value).map_err(Error::Parse)?);
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(name) = profile_flag() {
+        config
+            .find_profile(name)
+            .map_err(Error::Config)?
+            .apply(&mut chat);
+        debug!("Using profile from --profile: {}", name);
+        chat.profile = Some(name.to_string());
+    }
+
+    if !chat.private {
+        debug!(
+            "Final system prompt from frontmatter: {}",
+            chat.system_prompt
+        );
+    }
+    debug!("Final model from frontmatter: {}", chat.model);
+    debug!("Final API endpoint from frontmatter: {}", chat.api_endpoint);
+
+    Ok(chat)
+}
+
+/// Reads the lines between the opening and closing `---` delimiters.
+fn read_frontmatter_block<B: BufRead>(lines: &mut std::io::Lines<B>) -> Result<Vec<String>> {
+    let mut block = Vec::new();
     let mut in_frontmatter = false;
-    let mut current_key = String::new();
-    let mut current_value = String::new();
 
     for line in lines {
         let line = line?;
@@ -55,48 +306,15 @@ fn parse_frontmatter<B: BufRead>(
         }
 
         if in_frontmatter {
-            if let Some((key, value)) = line.split_once(':') {
-                // Save the previous key-value pair
-                if !current_key.is_empty() {
-                    match current_key.as_str() {
-                        "system" => system_prompt = current_value.trim().to_string(),
-                        "model" => model = current_value.trim().to_string(),
-                        "api_endpoint" => api_endpoint = current_value.trim().to_string(),
-                        _ => {}
-                    }
-                }
-                // Start a new key-value pair
-                current_key = key.trim().to_string();
-                current_value = value.trim().to_string();
-            } else {
-                // Continue accumulating the value for the current key
-                current_value.push('\n');
-                current_value.push_str(line.trim());
-            }
+            block.push(line);
         }
     }
 
-    // Save the last key-value pair
-    if !current_key.is_empty() {
-        match current_key.as_str() {
-            "system" => system_prompt = current_value.trim().to_string(),
-            "model" => model = current_value.trim().to_string(),
-            "api_endpoint" => api_endpoint = current_value.trim().to_string(),
-            _ => {}
-        }
-    }
-
-    debug!("Final system prompt from frontmatter: {}", system_prompt);
-    debug!("Final model from frontmatter: {}", model);
-    debug!("Final API endpoint from frontmatter: {}", api_endpoint);
-
-    Ok((system_prompt, model, api_endpoint))
+    Ok(block)
 }
 
 /// Parses the messages section of the file to extract role and content of each message.
-fn parse_messages<B: BufRead>(
-    lines: &mut std::io::Lines<B>,
-) -> Result<Vec<(String, String)>, IoError> {
+fn parse_messages<B: BufRead>(lines: &mut std::io::Lines<B>) -> Result<Vec<(String, String)>> {
     let mut messages = Vec::new();
     let mut current_role = String::new();
     let mut current_content = String::new();
@@ -104,30 +322,26 @@ fn parse_messages<B: BufRead>(
     for line in lines {
         let line = line?;
         if is_new_message(&line) {
-            finish_current_message(&mut messages, &mut current_role, &mut current_content);
+            finish_current_message(&mut messages, &current_role, &mut current_content);
             start_new_message(&line, &mut current_role, &mut current_content);
         } else if !line.is_empty() {
             process_message_line(&line, &current_role, &mut current_content)?;
         }
     }
 
-    finish_current_message(&mut messages, &mut current_role, &mut current_content);
+    finish_current_message(&mut messages, &current_role, &mut current_content);
     Ok(messages)
 }
 
 /// Checks if a line indicates the start of a new message.
 fn is_new_message(line: &str) -> bool {
-    line.starts_with("user:") || line.starts_with("assistant:")
+    entry_role(line).is_some()
 }
 
 /// Finalizes the current message being processed.
-fn finish_current_message(
-    messages: &mut Vec<(String, String)>,
-    role: &mut String,
-    content: &mut String,
-) {
+fn finish_current_message(messages: &mut Vec<(String, String)>, role: &str, content: &mut String) {
     if !role.is_empty() {
-        messages.push((role.clone(), content.trim().to_string()));
+        messages.push((role.to_string(), content.trim().to_string()));
         content.clear();
     }
 }
@@ -140,11 +354,14 @@ fn start_new_message(line: &str, role: &mut String, content: &mut String) {
         "assistant"
     }
     .to_string();
-    *content = line.splitn(2, ':').nth(1).unwrap_or("").trim().to_string();
+    *content = line
+        .split_once(':')
+        .map(|(_, rest)| rest.trim().to_string())
+        .unwrap_or_default();
 }
 
 /// Processes a line of a message based on the role.
-fn process_message_line(line: &str, role: &str, content: &mut String) -> Result<(), IoError> {
+fn process_message_line(line: &str, role: &str, content: &mut String) -> Result<()> {
     match role {
         "user" => process_user_line(line, content),
         "assistant" => process_assistant_line(line, content),
@@ -153,17 +370,17 @@ fn process_message_line(line: &str, role: &str, content: &mut String) -> Result<
 }
 
 /// Processes a line of a user message.
-fn process_user_line(line: &str, content: &mut String) -> Result<(), IoError> {
+fn process_user_line(line: &str, content: &mut String) -> Result<()> {
     if is_file_reference(line) {
         expand_file_reference(line, content)?;
-    } else if !line.starts_with("<c>") {
+    } else if !line.starts_with("<c>") && !is_directive_comment(line) {
         append_line(content, line);
     }
     Ok(())
 }
 
 /// Processes a line of an assistant message.
-fn process_assistant_line(line: &str, content: &mut String) -> Result<(), IoError> {
+fn process_assistant_line(line: &str, content: &mut String) -> Result<()> {
     if !line.trim().starts_with("<!--") && !line.trim().ends_with("-->") {
         append_line(content, line);
     }
@@ -176,17 +393,17 @@ fn is_file_reference(line: &str) -> bool {
 }
 
 /// Expands a file reference line to include the content of the referenced file.
-fn expand_file_reference(line: &str, content: &mut String) -> Result<(), IoError> {
+fn expand_file_reference(line: &str, content: &mut String) -> Result<()> {
     let file_path = line.trim().trim_start_matches("[[").trim_end_matches("]]");
     match fs::read_to_string(file_path) {
         Ok(file_content) => {
             content.push_str(&format!("\n\n[[{}]]\n\n{}\n\n", file_path, file_content));
             Ok(())
         }
-        Err(e) => {
-            content.push_str(&format!("\n\nFailed to read file: {}\n\n", file_path));
-            Err(e)
-        }
+        Err(source) => Err(Error::Reference {
+            path: file_path.to_string(),
+            source,
+        }),
     }
 }
 
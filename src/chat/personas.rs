@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::chat::config::{get_config_dir, read_yaml_file};
+use crate::chat::parser::ParsedChat;
+
+/// Directory, under `~/.samvada`, that holds one YAML file per persona.
+const PERSONAS_DIR_NAME: &str = "personas";
+
+/// A named preset of system prompt, model and request parameters.
+///
+/// Personas come from the `personas:` map in the config or from
+/// `~/.samvada/personas/<name>.yaml`, and are selected with `persona: <name>`
+/// in a chat's frontmatter or `quick --persona <name>`.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct Persona {
+    #[serde(default)]
+    pub system: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub params: Map<String, Value>,
+}
+
+impl Persona {
+    /// Sets the persona's system prompt, model and params on a chat.
+    pub fn apply(&self, chat: &mut ParsedChat) {
+        if let Some(system) = &self.system {
+            chat.system_prompt = system.clone();
+        }
+        if let Some(model) = &self.model {
+            chat.model = model.clone();
+        }
+        chat.params.extend(self.params.clone());
+    }
+}
+
+/// Finds a persona by name, first in the config's `personas:` map, then in
+/// `~/.samvada/personas/`.
+pub fn find_persona(name: &str, configured: &HashMap<String, Persona>) -> Result<Persona, String> {
+    if let Some(persona) = configured.get(name) {
+        return Ok(persona.clone());
+    }
+
+    let path = persona_file(name).ok_or_else(|| {
+        format!(
+            "unknown persona '{}'. Add it under `personas:` in config.yaml or as ~/.samvada/personas/{}.yaml",
+            name, name
+        )
+    })?;
+    read_yaml_file(&path).map_err(|e| format!("Failed to load persona {}: {}", path.display(), e))
+}
+
+fn persona_file(name: &str) -> Option<PathBuf> {
+    if name.contains(['/', '\\']) {
+        return None;
+    }
+    let dir = get_config_dir().ok()?.join(PERSONAS_DIR_NAME);
+    ["yaml", "yml"]
+        .iter()
+        .map(|extension| dir.join(format!("{}.{}", name, extension)))
+        .find(|path| path.is_file())
+}
@@ -4,51 +4,56 @@ use clap::{Arg, ArgMatches, Command};
 use log::{error, info};
 use serde_json::Value;
 use std::io::{self, Read};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
+use crate::chat::_utils::handle_openai_response;
 use crate::chat::api::query_openai;
+use crate::chat::budget::check_budget;
+use crate::chat::cache::{self, CacheMode};
+use crate::chat::cassette;
 use crate::chat::config::load_config;
-use crate::chat::config::{get_api_key, get_env_file_path, save_api_key};
-use crate::chat::create::create_chat;
+use crate::chat::config::{get_api_key, get_env_file_path, profile_flag, save_api_key};
+use crate::chat::create::{create_chat, ChatOptions};
+use crate::chat::credentials::endpoint_host;
+use crate::chat::dry_run::print_dry_run;
 use crate::chat::logging::setup_logging;
-use crate::chat::parser::prepare_api_messages;
-use crate::chat::_utils::handle_openai_response;
+use crate::chat::parser::{chat_from_config, prepare_api_messages, ParsedChat};
+use crate::chat::personas::find_persona;
+use crate::chat::usage;
+use crate::error::{Error, Result};
 
 /// Handles the quick subcommand by saving API key, loading environment variables, processing the question, and querying OpenAI.
-pub async fn handle_quick_subcommand(matches: &ArgMatches) {
-    // Setup logging FIRST (similar to ask.rs)
-    let _log_path = setup_logging(None);
-    info!("Starting processing for quick question");
+pub async fn handle_quick_subcommand(matches: &ArgMatches) -> Result<()> {
+    let dry_run = matches.get_one::<String>("dry-run");
 
-    // Load configurations from the YAML file
-    let app_config = match load_config() {
-        Ok(cfg) => cfg,
-        Err(e) => {
-            eprintln!("Error loading config: {}", e);
-            std::process::exit(1);
+    // Setup logging FIRST (similar to ask.rs); a dry run leaves no files behind
+    if dry_run.is_none() {
+        if let Err(e) = setup_logging(None) {
+            eprintln!("Warning: {}; continuing without a log", e);
         }
-    };
+    }
+    info!("Starting processing for quick question");
 
-    // Use the loaded configurations
-    let system_prompt = app_config.system_prompt;
-    let model = app_config.model;
-    let api_endpoint = app_config.api_endpoint;
+    // Load configurations from the YAML file
+    let app_config = load_config()?;
 
-    // If API key is provided as argument, save it
-    if let Some(api_key) = matches.get_one::<String>("api-key") {
-        if let Err(e) = save_api_key(api_key) {
-            eprintln!("Failed to save API key: {}", e);
-            std::process::exit(1);
-        }
-    }
+    // Use the loaded configurations and the active profile
+    let mut chat = chat_from_config(&app_config)?;
 
-    // Load environment variables from the config directory
-    if let Ok(env_path) = get_env_file_path() {
-        dotenv::from_path(env_path).ok();
+    if let Some(name) = matches.get_one::<String>("persona") {
+        let persona = find_persona(name, &app_config.personas).map_err(Error::Config)?;
+        persona.apply(&mut chat);
+        chat.persona = Some(name.clone());
     }
 
-    let api_key = get_api_key(matches.get_one::<String>("api-key"));
-
     // Read question from argument or stdin
+    let no_question = || {
+        Error::Usage(
+            "No question provided. Please provide a question as an argument or via stdin."
+                .to_string(),
+        )
+    };
     let question = if let Some(question_arg) = matches.get_one::<String>("question") {
         question_arg.clone()
     } else if !atty::is(Stream::Stdin) {
@@ -56,62 +61,101 @@ pub async fn handle_quick_subcommand(matches: &ArgMatches) {
         let mut stdin_input = String::new();
         io::stdin()
             .read_to_string(&mut stdin_input)
-            .expect("Failed to read from stdin");
+            .map_err(|e| Error::io("Failed to read from stdin", e))?;
         stdin_input.trim().to_string()
     } else {
-        eprintln!("No question provided. Please provide a question as an argument or via stdin.");
-        std::process::exit(1);
+        return Err(no_question());
     };
 
     // Ensure that the question is not empty
     if question.is_empty() {
-        eprintln!("No question provided. Please provide a question as an argument or via stdin.");
-        std::process::exit(1);
+        return Err(no_question());
     }
 
-    match process_question_and_query_openai(
-        &question,
-        &api_key,
-        &system_prompt,
-        &model,
-        &api_endpoint,
-    )
-    .await
-    {
-        Ok((answer, response_body)) => {
-            println!("\n{}\n", answer);
-            info!("Successfully processed question and received answer");
-
-            // Handle logging and saving if required
-            if matches.get_flag("save-to-markdown") {
-                if let Err(e) = save_conversation_to_markdown(&question, &answer, &response_body) {
-                    error!("Failed to save conversation to markdown: {}", e);
-                    eprintln!("Failed to save conversation to markdown: {}", e);
-                } else {
-                    info!("Successfully saved conversation to markdown");
-                }
-            }
-        }
-        Err(e) => {
-            error!("Error processing question and querying OpenAI: {}", e);
-            eprintln!("Error processing question and querying OpenAI: {}", e);
-            std::process::exit(1);
-        }
+    if let Some(format) = dry_run {
+        let messages = vec![("user".to_string(), question)];
+        let api_messages = prepare_api_messages(&chat.system_prompt, &messages);
+        print_dry_run(&chat, api_messages, format, &app_config);
+        return Ok(());
     }
+
+    cache::init(&app_config.cache, CacheMode::from_matches(matches));
+
+    // If API key is provided as argument, save it for the endpoint
+    if let Some(api_key) = matches.get_one::<String>("api-key") {
+        save_api_key(api_key, &endpoint_host(&chat.api_endpoint))?;
+    }
+
+    // Load environment variables from the config directory
+    if let Ok(env_path) = get_env_file_path() {
+        dotenv::from_path(env_path).ok();
+    }
+
+    // Replayed answers don't need a key, so cassettes can be used without one
+    let api_key = if cassette::replaying() {
+        String::new()
+    } else {
+        get_api_key(matches.get_one::<String>("api-key"), &chat)?
+    };
+
+    check_budget(
+        None,
+        chat.profile.as_deref(),
+        matches.get_flag("override-budget"),
+    )?;
+
+    let started = Instant::now();
+    let (answer, response_body) = process_question_and_query_openai(&question, &api_key, &chat)
+        .await
+        .inspect_err(|e| error!("Error processing question and querying OpenAI: {}", e))?;
+    let latency = started.elapsed();
+    println!("\n{}\n", answer);
+    info!("Successfully processed question and received answer");
+
+    // Handle logging and saving if required
+    let saved = matches.get_flag("save-to-markdown").then(|| {
+        save_conversation_to_markdown(
+            &question,
+            &answer,
+            &response_body,
+            latency,
+            chat.persona.clone(),
+        )
+        .map_err(|e| e.context("Failed to save conversation to markdown"))
+        .inspect_err(|e| error!("{}", e))
+    });
+    // The answer was paid for even if it couldn't be saved
+    let saved_to = saved.as_ref().and_then(|saved| saved.as_ref().ok());
+    usage::record(
+        &chat,
+        saved_to.map(PathBuf::as_path),
+        &response_body,
+        latency,
+    );
+    if let Some(saved) = saved {
+        saved?;
+        info!("Successfully saved conversation to markdown");
+    }
+    Ok(())
 }
 
 /// Processes the provided question and queries OpenAI, returning the answer and response body.
 async fn process_question_and_query_openai(
     question: &str,
     api_key: &str,
-    system_prompt: &str,
-    model: &str,
-    api_endpoint: &str,
-) -> Result<(String, Value), Box<dyn std::error::Error>> {
+    chat: &ParsedChat,
+) -> Result<(String, Value)> {
     let messages = vec![("user".to_string(), question.to_string())];
-    let api_messages = prepare_api_messages(system_prompt, &messages);
+    let api_messages = prepare_api_messages(&chat.system_prompt, &messages);
 
-    query_openai(api_key, model, api_endpoint, api_messages).await
+    query_openai(
+        api_key,
+        &chat.model,
+        &chat.api_endpoint,
+        api_messages,
+        &chat.params,
+    )
+    .await
 }
 
 /// Saves the conversation between user and assistant to a markdown file with necessary metadata.
@@ -119,17 +163,22 @@ fn save_conversation_to_markdown(
     question: &str,
     answer: &str,
     response_body: &Value,
-) -> std::io::Result<()> {
+    latency: Duration,
+    persona: Option<String>,
+) -> Result<PathBuf> {
     let timestamp = Local::now();
     let file_name = format!("conversation_{}", timestamp.format("%Y%m%d_%H%M%S"));
 
     // First create the file with proper frontmatter using create_chat
-    create_chat(&file_name, None)?;
+    let options = ChatOptions {
+        persona,
+        profile: profile_flag().map(str::to_string),
+        ..ChatOptions::default()
+    };
+    let file_path = create_chat(&file_name, None, &options, false)?;
 
     // Now append the conversation
-    let current_dir = std::env::current_dir()?;
-    let file_path = current_dir.join(format!("{}.md", file_name));
-    
+
     // Convert PathBuf to &str
     let file_path_str = file_path.to_str().ok_or_else(|| {
         io::Error::new(
@@ -138,10 +187,16 @@ fn save_conversation_to_markdown(
         )
     })?;
 
-    handle_openai_response(file_path_str, Some(question), answer, response_body)?;
+    handle_openai_response(
+        file_path_str,
+        Some(question),
+        answer,
+        response_body,
+        latency,
+    )?;
     println!("\nSaving conversation to: {}", file_path.display());
 
-    Ok(())
+    Ok(file_path)
 }
 
 /// Defines the 'quick' command for asking a question to OpenAI with options to save the conversation to markdown.
@@ -161,10 +216,51 @@ pub fn quick_command() -> Command {
                 .required(false)
                 .num_args(1),
         )
+        .arg(
+            Arg::new("override-budget")
+                .long("override-budget")
+                .help("Send the request even if a budget cap has been reached")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("persona")
+                .long("persona")
+                .help("Persona to use for the system prompt, model and params")
+                .num_args(1),
+        )
         .arg(
             Arg::new("save-to-markdown")
                 .long("save-to-markdown")
                 .help("Save the conversation to a markdown file")
                 .action(clap::ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("cache")
+                .long("cache")
+                .help("Answer from the response cache if possible, even if it isn't enabled in the config")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("force-cache")
+                .long("force-cache")
+                .help("Use the response cache even when the temperature is above 0")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("no-cache")
+                .long("no-cache")
+                .help("Don't use the response cache")
+                .conflicts_with_all(["cache", "force-cache"])
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .help("Print the request and its estimated tokens and cost instead of sending it")
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_missing_value("json")
+                .value_parser(["json", "text"])
+                .value_name("FORMAT"),
+        )
 }
\ No newline at end of file
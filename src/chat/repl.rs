@@ -0,0 +1,609 @@
+use chrono::Utc;
+use clap::{Arg, ArgMatches, Command};
+use log::{debug, info, warn};
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Cmd, Context, Editor, EventHandler, Helper, KeyCode, KeyEvent, Modifiers};
+use serde_json::json;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::chat::_utils::handle_openai_response;
+use crate::chat::api::stream_openai;
+use crate::chat::budget::check_budget;
+use crate::chat::cache::{self, CacheMode};
+use crate::chat::cassette;
+use crate::chat::config::{
+    get_api_key, get_config_dir, get_env_file_path, load_config_for, AppConfig,
+};
+use crate::chat::credentials::write_file_atomically;
+use crate::chat::dry_run::{estimate_text_tokens, estimate_tokens};
+use crate::chat::lint::{entry_role, find_frontmatter};
+use crate::chat::logging::{set_private, setup_logging};
+use crate::chat::parser::{parse_file, prepare_api_messages, ParsedChat};
+use crate::chat::usage;
+use crate::error::{Error, Result};
+
+/// Name of the REPL's history file in `~/.samvada`.
+const HISTORY_FILE_NAME: &str = "repl_history";
+
+/// Slash commands, with the help shown by `/help`.
+const COMMANDS: &[(&str, &str)] = &[
+    (
+        "/model",
+        "/model [name]      Show or change the model for this session",
+    ),
+    (
+        "/system",
+        "/system [prompt]   Show or change the system prompt for this session",
+    ),
+    (
+        "/retry",
+        "/retry             Ask the last question again, replacing its answer",
+    ),
+    (
+        "/undo",
+        "/undo              Remove the last question and its answer",
+    ),
+    (
+        "/file",
+        "/file <path>       Attach a file to your next message as a [[reference]]",
+    ),
+    (
+        "/tokens",
+        "/tokens            Estimate the tokens and cost of the conversation",
+    ),
+    (
+        "/save-as",
+        "/save-as <path>    Copy the chat to a new file and continue there",
+    ),
+    ("/help", "/help              Show this help"),
+    (
+        "/exit",
+        "/exit              Leave the REPL (or press Ctrl-D)",
+    ),
+];
+
+/// An interactive session on a chat file.
+struct Session {
+    file: PathBuf,
+    config: AppConfig,
+    api_key: String,
+    override_budget: bool,
+    /// Model chosen with `/model`, over the chat's.
+    model: Option<String>,
+    /// System prompt chosen with `/system`, over the chat's.
+    system: Option<String>,
+    /// Files attached with `/file`, sent with the next message.
+    attachments: Vec<String>,
+}
+
+/// Handles the repl subcommand: reads questions until Ctrl-D or `/exit`, appending each turn to the file.
+pub async fn handle_repl_subcommand(matches: &ArgMatches) -> Result<()> {
+    let file_path = matches.get_one::<String>("file").unwrap();
+
+    if let Err(e) = setup_logging(Some(file_path)) {
+        eprintln!("Warning: {}; continuing without a log", e);
+    }
+    info!("Starting REPL for file: {}", file_path);
+
+    if let Ok(env_path) = get_env_file_path() {
+        dotenv::from_path(env_path).ok();
+    }
+
+    let chat = parse_file(file_path)?;
+    set_private(chat.private);
+    let config = load_config_for(Path::new(file_path))?;
+    cache::init(&config.cache, CacheMode::Config);
+
+    // Replayed answers don't need a key, so cassettes can be used without one
+    let api_key = if cassette::replaying() {
+        String::new()
+    } else {
+        get_api_key(matches.get_one::<String>("api-key"), &chat)?
+    };
+
+    let mut session = Session {
+        file: PathBuf::from(file_path),
+        config,
+        api_key,
+        override_budget: matches.get_flag("override-budget"),
+        model: None,
+        system: None,
+        attachments: Vec::new(),
+    };
+
+    let mut editor: Editor<ReplHelper, DefaultHistory> = Editor::new()
+        .map_err(|e| Error::io("Failed to start the line editor", io::Error::other(e)))?;
+    editor.set_helper(Some(ReplHelper));
+    editor.bind_sequence(
+        KeyEvent(KeyCode::Enter, Modifiers::ALT),
+        EventHandler::Simple(Cmd::Newline),
+    );
+    // Messages from private chats are kept out of the history file
+    let history = get_config_dir()
+        .ok()
+        .map(|dir| dir.join(HISTORY_FILE_NAME))
+        .filter(|_| !chat.private);
+    if let Some(history) = &history {
+        let _ = editor.load_history(history);
+    }
+
+    println!(
+        "Chatting in {} with {}. Type /help for commands, Ctrl-D to leave.",
+        session.file.display(),
+        chat.model
+    );
+    loop {
+        let input = match editor.readline("you> ") {
+            Ok(input) => input,
+            // Ctrl-C clears the line being typed
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(Error::io("Failed to read input", io::Error::other(e))),
+        };
+        let input = multi_line_input(&input);
+        if input.is_empty() {
+            continue;
+        }
+        if history.is_some() {
+            let _ = editor.add_history_entry(input.as_str());
+        }
+
+        let result = if let Some(text) = input.strip_prefix("//") {
+            session.send(&format!("/{}", text)).await
+        } else if is_command(&input) {
+            match session.command(&input).await {
+                Ok(true) => Ok(()),
+                Ok(false) => break,
+                Err(e) => Err(e),
+            }
+        } else {
+            session.send(&input).await
+        };
+        if let Err(e) = result {
+            eprintln!("Error: {}", e);
+        }
+    }
+
+    if let Some(history) = &history {
+        let saved = create_private(history)
+            .and_then(|_| editor.save_history(history).map_err(io::Error::other));
+        if let Err(e) = saved {
+            warn!("Failed to save the REPL history: {}", e);
+        }
+    }
+    Ok(())
+}
+
+/// Whether the input's first word is one of the slash commands.
+///
+/// Other lines starting with `/`, like a path, are sent as messages.
+fn is_command(input: &str) -> bool {
+    let first = input.split_whitespace().next().unwrap_or_default();
+    first == "/quit" || COMMANDS.iter().any(|(command, _)| *command == first)
+}
+
+/// Creates `path` if it is missing, readable and writable only by the current user.
+fn create_private(path: &Path) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.append(true).create(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        options.open(path)?;
+        // `mode` only applies to new files, so tighten an existing one too
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+    #[cfg(not(unix))]
+    options.open(path)?;
+    Ok(())
+}
+
+impl Session {
+    /// Runs a slash command, returning whether to keep going.
+    async fn command(&mut self, input: &str) -> Result<bool> {
+        let (command, argument) = input
+            .split_once(char::is_whitespace)
+            .map_or((input, ""), |(command, argument)| {
+                (command, argument.trim())
+            });
+        match command {
+            "/model" if argument.is_empty() => println!("Model: {}", self.chat()?.model),
+            "/model" => {
+                self.model = Some(argument.to_string());
+                println!("Using {} for the rest of this session.", argument);
+            }
+            "/system" if argument.is_empty() => {
+                println!("System prompt: {}", self.chat()?.system_prompt)
+            }
+            "/system" => {
+                self.system = Some(argument.to_string());
+                println!("Changed the system prompt for the rest of this session.");
+            }
+            "/retry" => {
+                let content = self.read()?;
+                if let Some(content) = remove_last_answer(&content) {
+                    self.write(&content)?;
+                } else if !ends_with_question(&content) {
+                    return Err(Error::Usage("There is no question to retry.".to_string()));
+                }
+                self.ask().await?;
+            }
+            "/undo" => match remove_last_exchange(&self.read()?) {
+                Some(content) => {
+                    self.write(&content)?;
+                    println!("Removed the last question and its answer.");
+                }
+                None => println!("There is nothing to undo."),
+            },
+            "/file" if argument.is_empty() => {
+                return Err(Error::Usage("Usage: /file <path>".to_string()))
+            }
+            "/file" => {
+                if !Path::new(argument).is_file() {
+                    return Err(Error::Usage(format!("{} is not a file.", argument)));
+                }
+                self.attachments.push(argument.to_string());
+                println!(
+                    "Attached {}; it will be sent with your next message.",
+                    argument
+                );
+            }
+            "/tokens" => self.print_tokens()?,
+            "/save-as" if argument.is_empty() => {
+                return Err(Error::Usage("Usage: /save-as <path>".to_string()))
+            }
+            "/save-as" => self.save_as(Path::new(argument))?,
+            "/help" => {
+                for (_, help) in COMMANDS {
+                    println!("  {}", help);
+                }
+                println!("End a line with \\ or press Alt-Enter to continue on a new line.");
+                println!("Start a message with // to send it with a single leading /.");
+            }
+            "/exit" | "/quit" => return Ok(false),
+            _ => {
+                return Err(Error::Usage(format!(
+                    "Unknown command {}. Type /help for the list.",
+                    command
+                )))
+            }
+        }
+        Ok(true)
+    }
+
+    /// Appends a question, with any attached files, to the chat and asks it.
+    async fn send(&mut self, question: &str) -> Result<()> {
+        let mut question = question.to_string();
+        for attachment in self.attachments.drain(..) {
+            question.push_str(&format!("\n[[{}]]", attachment));
+        }
+        let content = self.read()?;
+        self.append(&question_suffix(&content, &question))?;
+        self.ask().await
+    }
+
+    /// Streams the answer to the chat's last question and appends it to the file.
+    ///
+    /// Ctrl-C stops the answer; what arrived so far is kept.
+    async fn ask(&self) -> Result<()> {
+        let chat = self.chat()?;
+        check_budget(
+            Some(&self.file),
+            chat.profile.as_deref(),
+            self.override_budget,
+        )?;
+        let api_messages = prepare_api_messages(&chat.system_prompt, &chat.messages);
+        let prompt_tokens = estimate_tokens(&api_messages);
+
+        let started = Instant::now();
+        let mut partial = String::new();
+        let mut on_delta = |delta: &str| {
+            print!("{}", delta);
+            let _ = io::stdout().flush();
+            partial.push_str(delta);
+        };
+        let outcome = tokio::select! {
+            result = stream_openai(
+                &self.api_key,
+                &chat.model,
+                &chat.api_endpoint,
+                api_messages,
+                &chat.params,
+                &mut on_delta,
+            ) => Some(result),
+            _ = tokio::signal::ctrl_c() => None,
+        };
+        let latency = started.elapsed();
+        println!("\n");
+
+        let file_path = self.file_str()?;
+        let append_failed = |e| Error::io("Failed to append answer to file", e);
+        match outcome {
+            Some(result) => {
+                let (answer, response_body) = result?;
+                if response_body["usage"].is_null() {
+                    // The endpoint didn't report usage for the stream
+                    let completion_tokens = estimate_text_tokens(&answer);
+                    usage::record_estimated(
+                        &chat,
+                        Some(&self.file),
+                        prompt_tokens,
+                        completion_tokens,
+                        latency,
+                    );
+                } else {
+                    usage::record(&chat, Some(&self.file), &response_body, latency);
+                }
+                handle_openai_response(file_path, None, &answer, &response_body, latency)
+                    .map_err(append_failed)?;
+            }
+            None if partial.is_empty() => {
+                usage::record_estimated(&chat, Some(&self.file), prompt_tokens, 0, latency);
+                println!("Cancelled. Your question is kept; /retry to ask it again.");
+            }
+            None => {
+                debug!("Cancelled after {} characters", partial.len());
+                let completion_tokens = estimate_text_tokens(&partial);
+                usage::record_estimated(
+                    &chat,
+                    Some(&self.file),
+                    prompt_tokens,
+                    completion_tokens,
+                    latency,
+                );
+                let response_body = json!({
+                    "model": chat.model,
+                    "created": Utc::now().timestamp(),
+                    "choices": [{"finish_reason": "cancelled"}],
+                });
+                handle_openai_response(file_path, None, &partial, &response_body, latency)
+                    .map_err(append_failed)?;
+                println!("Cancelled. The partial answer was saved; /retry to ask again.");
+            }
+        }
+        Ok(())
+    }
+
+    /// The chat as it is on disk, with the session's `/model` and `/system` changes.
+    fn chat(&self) -> Result<ParsedChat> {
+        let mut chat = parse_file(self.file_str()?)?;
+        if let Some(model) = &self.model {
+            chat.model = model.clone();
+        }
+        if let Some(system) = &self.system {
+            chat.system_prompt = system.clone();
+        }
+        Ok(chat)
+    }
+
+    fn print_tokens(&self) -> Result<()> {
+        let chat = self.chat()?;
+        let api_messages = prepare_api_messages(&chat.system_prompt, &chat.messages);
+        let tokens = estimate_tokens(&api_messages);
+        print!("About {} tokens in {} messages", tokens, api_messages.len());
+        match self.config.price_for(&chat.model) {
+            Some(price) => println!(
+                ", about ${:.6} per request with {}, plus the answer.",
+                price.cost(tokens, 0),
+                chat.model
+            ),
+            None => println!("."),
+        }
+        Ok(())
+    }
+
+    fn save_as(&mut self, path: &Path) -> Result<()> {
+        if path.exists() {
+            return Err(Error::Usage(format!("{} already exists.", path.display())));
+        }
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)
+                .map_err(|e| Error::io(format!("Failed to create {}", dir.display()), e))?;
+        }
+        fs::copy(&self.file, path)
+            .map_err(|e| Error::io(format!("Failed to write {}", path.display()), e))?;
+        self.file = path.to_path_buf();
+        println!("Saved to {}; continuing there.", path.display());
+        Ok(())
+    }
+
+    fn file_str(&self) -> Result<&str> {
+        self.file
+            .to_str()
+            .ok_or_else(|| Error::Usage(format!("{} is not valid Unicode.", self.file.display())))
+    }
+
+    fn read(&self) -> Result<String> {
+        fs::read_to_string(&self.file)
+            .map_err(|e| Error::io(format!("Failed to read {}", self.file.display()), e))
+    }
+
+    /// Replaces the file in one step, so an interruption never leaves it half written.
+    fn write(&self, content: &str) -> Result<()> {
+        write_file_atomically(&self.file, content.as_bytes())
+            .map_err(|e| Error::io(format!("Failed to write {}", self.file.display()), e))
+    }
+
+    fn append(&self, text: &str) -> Result<()> {
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&self.file)
+            .and_then(|mut file| file.write_all(text.as_bytes()))
+            .map_err(|e| Error::io(format!("Failed to write {}", self.file.display()), e))
+    }
+}
+
+/// Joins lines continued with a trailing `\`, and unwraps `"""` blocks.
+fn multi_line_input(input: &str) -> String {
+    let input = input.replace("\\\n", "\n");
+    let input = input.trim();
+    match input
+        .strip_prefix("\"\"\"")
+        .and_then(|rest| rest.strip_suffix("\"\"\""))
+    {
+        Some(block) => block.trim().to_string(),
+        None => input.to_string(),
+    }
+}
+
+/// The role and first line of each entry after the frontmatter.
+fn entries(lines: &[&str]) -> Vec<(&'static str, usize)> {
+    let body_start = find_frontmatter(lines).map_or(0, |(_, end)| end + 1);
+    (body_start..lines.len())
+        .filter_map(|index| entry_role(lines[index]).map(|role| (role, index)))
+        .collect()
+}
+
+/// Whether an entry has no text after its role marker.
+fn is_empty_entry(lines: &[&str], start: usize, end: usize) -> bool {
+    let marker = lines[start].split_once(':').map_or("", |(_, rest)| rest);
+    marker.trim().is_empty()
+        && lines[start + 1..end]
+            .iter()
+            .all(|line| line.trim().is_empty())
+}
+
+/// The text that appends a question to `content`: after the trailing empty `user:`
+/// if there is one, else as a new entry.
+fn question_suffix(content: &str, question: &str) -> String {
+    let lines: Vec<&str> = content.lines().collect();
+    match entries(&lines).last() {
+        Some(("user", start)) if is_empty_entry(&lines, *start, lines.len()) => {
+            let separator = if content.ends_with('\n') { "" } else { "\n" };
+            format!("{}{}\n\n", separator, question)
+        }
+        _ => {
+            let separator = if content.is_empty() || content.ends_with("\n\n") {
+                ""
+            } else if content.ends_with('\n') {
+                "\n"
+            } else {
+                "\n\n"
+            };
+            format!("{}user:\n{}\n\n", separator, question)
+        }
+    }
+}
+
+/// Entries that have text, ignoring a trailing empty `user:`.
+fn answered_entries(lines: &[&str]) -> Vec<(&'static str, usize)> {
+    let mut entries = entries(lines);
+    if let Some(&("user", start)) = entries.last() {
+        if is_empty_entry(lines, start, lines.len()) {
+            entries.pop();
+        }
+    }
+    entries
+}
+
+/// Whether the chat ends with a question that hasn't been answered.
+fn ends_with_question(content: &str) -> bool {
+    let lines: Vec<&str> = content.lines().collect();
+    matches!(answered_entries(&lines).last(), Some(("user", _)))
+}
+
+/// Removes the last answer, leaving its question at the end, or `None` if the chat doesn't end with an answer.
+fn remove_last_answer(content: &str) -> Option<String> {
+    let lines: Vec<&str> = content.lines().collect();
+    match answered_entries(&lines).last() {
+        Some(&("assistant", start)) => {
+            Some(format!("{}\n\n", lines[..start].join("\n").trim_end()))
+        }
+        _ => None,
+    }
+}
+
+/// Removes the last question and its answer, leaving an empty `user:` for the next question.
+fn remove_last_exchange(content: &str) -> Option<String> {
+    let lines: Vec<&str> = content.lines().collect();
+    let entries = answered_entries(&lines);
+    let start = match entries.as_slice() {
+        [.., ("user", start), ("assistant", _)] => *start,
+        [.., ("user", start)] => *start,
+        _ => return None,
+    };
+    Some(format!(
+        "{}\n\nuser:\n",
+        lines[..start].join("\n").trim_end()
+    ))
+}
+
+/// Line editing help: slash command completion and multi-line input.
+struct ReplHelper;
+
+impl Helper for ReplHelper {}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let typed = &line[..pos];
+        if !typed.starts_with('/') || typed.contains(char::is_whitespace) {
+            return Ok((0, Vec::new()));
+        }
+        let candidates = COMMANDS
+            .iter()
+            .map(|(command, _)| command.to_string())
+            .filter(|command| command.starts_with(typed))
+            .collect();
+        Ok((0, candidates))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {
+    /// Keeps reading after a trailing `\` or inside an unclosed `"""` block.
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        let input = ctx.input();
+        let open_block =
+            input.trim_start().starts_with("\"\"\"") && input.matches("\"\"\"").count() % 2 == 1;
+        if input.ends_with('\\') || open_block {
+            Ok(ValidationResult::Incomplete)
+        } else {
+            Ok(ValidationResult::Valid(None))
+        }
+    }
+}
+
+/// Creates and returns the 'repl' command with its arguments
+pub fn repl_command() -> Command {
+    Command::new("repl")
+        .about("Chat interactively, appending each turn to a chat file")
+        .arg(
+            Arg::new("file")
+                .help("Path to the chat file")
+                .required(true)
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("api-key")
+                .long("api-key")
+                .help("OpenAI API key to use for this session")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("override-budget")
+                .long("override-budget")
+                .help("Send requests even if a budget cap has been reached")
+                .action(clap::ArgAction::SetTrue),
+        )
+}
@@ -0,0 +1,191 @@
+//! Append-only JSON-lines log with one record per API request.
+//!
+//! Records go to `<chat>.requests.jsonl` next to the text log. The API key,
+//! `Authorization` values and anything that looks like a secret are replaced
+//! with `[REDACTED]` before a record is written.
+
+use chrono::{DateTime, Local};
+use log::warn;
+use regex::Regex;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use crate::chat::config::RequestLogConfig;
+use crate::chat::logging::log_content;
+use crate::error::Error;
+
+const REDACTED: &str = "[REDACTED]";
+
+struct RequestLog {
+    path: PathBuf,
+    config: RequestLogConfig,
+    run_id: String,
+}
+
+static REQUEST_LOG: OnceLock<RequestLog> = OnceLock::new();
+
+/// Starts logging requests to `path`; requests made before this are not logged.
+pub fn init(path: PathBuf, config: RequestLogConfig) {
+    let run_id = format!(
+        "{}-{}",
+        Local::now().format("%Y%m%dT%H%M%S"),
+        std::process::id()
+    );
+    let _ = REQUEST_LOG.set(RequestLog {
+        path,
+        config,
+        run_id,
+    });
+}
+
+/// Hashes a request body, so identical requests can be recognised across runs.
+///
+/// Object keys are serialized in sorted order, so the hash doesn't depend on
+/// the order parameters were written in.
+pub fn request_hash(body: &Value) -> String {
+    let digest = Sha256::digest(body.to_string().as_bytes());
+    format!("{:x}", digest)
+}
+
+/// A request that is under way, written to the log once it finishes.
+pub struct RequestRecord {
+    started_at: DateTime<Local>,
+    timer: Instant,
+    model: String,
+    endpoint: String,
+    body: Value,
+    hash: String,
+}
+
+impl RequestRecord {
+    pub fn start(model: &str, endpoint: &str, body: &Value) -> RequestRecord {
+        RequestRecord {
+            started_at: Local::now(),
+            timer: Instant::now(),
+            model: model.to_string(),
+            endpoint: endpoint.to_string(),
+            body: body.clone(),
+            hash: request_hash(body),
+        }
+    }
+
+    pub fn hash(&self) -> &str {
+        &self.hash
+    }
+
+    /// Logs the outcome: the HTTP status and body if there was a response, and the error if it failed.
+    pub fn finish(
+        self,
+        api_key: &str,
+        status: Option<u16>,
+        response: Option<&str>,
+        error: Option<&Error>,
+    ) {
+        let Some(log) = REQUEST_LOG.get() else {
+            return;
+        };
+        let response: Option<Value> = response.map(|text| {
+            serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_string()))
+        });
+
+        let mut record = json!({
+            "run_id": log.run_id,
+            "started_at": self.started_at.to_rfc3339(),
+            "finished_at": Local::now().to_rfc3339(),
+            "latency_ms": self.timer.elapsed().as_millis() as u64,
+            "model": self.model,
+            "endpoint": self.endpoint,
+            "status": status,
+            "error": error.map(|e| e.to_string()),
+            "usage": response.as_ref().and_then(|body| body.get("usage")).cloned(),
+            "request_hash": self.hash,
+        });
+        if log.config.payloads && log_content() {
+            record["request"] = self.body;
+            record["response"] = response.unwrap_or(Value::Null);
+        }
+
+        let line = scrub(&record.to_string(), api_key);
+        if let Err(e) = log.append(&line) {
+            warn!("Failed to write {}: {}", log.path.display(), e);
+        }
+    }
+}
+
+impl RequestLog {
+    fn append(&self, line: &str) -> io::Result<()> {
+        let size = fs::metadata(&self.path).map(|meta| meta.len()).unwrap_or(0);
+        if size > 0 && size + line.len() as u64 + 1 > self.config.max_size {
+            rotate(&self.path, self.config.keep)?;
+        }
+
+        let mut options = OpenOptions::new();
+        options.append(true).create(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            // Payloads may hold private conversations
+            options.mode(0o600);
+        }
+        let mut file = options.open(&self.path)?;
+        writeln!(file, "{}", line)
+    }
+}
+
+/// Shifts `log.1` to `log.2` and so on, dropping the oldest, then moves `log` to `log.1`.
+fn rotate(path: &Path, keep: usize) -> io::Result<()> {
+    let numbered = |n: usize| PathBuf::from(format!("{}.{}", path.display(), n));
+    if keep == 0 {
+        return fs::remove_file(path);
+    }
+    let _ = fs::remove_file(numbered(keep));
+    for n in (1..keep).rev() {
+        if numbered(n).exists() {
+            fs::rename(numbered(n), numbered(n + 1))?;
+        }
+    }
+    fs::rename(path, numbered(1))
+}
+
+/// Replaces the API key itself, if it is long enough not to match ordinary text.
+pub(crate) fn redact_key(text: &str, api_key: &str) -> String {
+    if api_key.len() >= 8 {
+        text.replace(api_key, REDACTED)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Replaces the API key, bearer tokens, `sk-` style keys and key-like URL parameters.
+///
+/// `text` is serialized JSON, so the patterns stop at quotes and backslashes.
+pub(crate) fn scrub(text: &str, api_key: &str) -> String {
+    static PATTERNS: OnceLock<Vec<(Regex, String)>> = OnceLock::new();
+    let patterns = PATTERNS.get_or_init(|| {
+        [
+            (r"(?i)\b(bearer\s+)[A-Za-z0-9._~+/=-]+", "${1}"),
+            (r"\bsk-[A-Za-z0-9_-]{16,}", ""),
+            (
+                r#"(?i)\b((?:api[_-]?)?key|token|secret|password)=[^&\s"\\]+"#,
+                "${1}=",
+            ),
+        ]
+        .into_iter()
+        .map(|(pattern, keep)| {
+            let regex = Regex::new(pattern).expect("Failed to compile secret regex.");
+            (regex, format!("{}{}", keep, REDACTED))
+        })
+        .collect()
+    });
+
+    let mut text = redact_key(text, api_key);
+    for (regex, replacement) in patterns {
+        text = regex.replace_all(&text, replacement.as_str()).into_owned();
+    }
+    text
+}
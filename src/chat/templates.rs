@@ -0,0 +1,295 @@
+use atty::Stream;
+use clap::{Arg, ArgMatches, Command};
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::chat::config::{get_config_dir, read_yaml_file};
+use crate::chat::create::ChatOptions;
+use crate::error::Error;
+
+/// Directory, under `~/.samvada` or a project's `.samvada`, that holds templates.
+const TEMPLATES_DIR_NAME: &str = "templates";
+
+/// A reusable starting point for `chat create --template`, stored as YAML.
+///
+/// Any string may contain `{{placeholders}}`, filled from `--var key=value`
+/// or prompted for interactively.
+#[derive(Debug, Default, Deserialize)]
+pub struct ChatTemplate {
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub system: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub params: Map<String, Value>,
+    /// Text of the first `user:` entry.
+    #[serde(default)]
+    pub message: Option<String>,
+    #[serde(default)]
+    pub persona: Option<String>,
+}
+
+/// Where a template was found.
+#[derive(Debug, Clone)]
+pub struct TemplateFile {
+    pub name: String,
+    pub path: PathBuf,
+    /// `project` or `global`.
+    pub origin: &'static str,
+}
+
+/// Handles the 'templates' subcommand.
+pub fn handle_templates_subcommand(matches: &ArgMatches) -> Result<(), Error> {
+    match matches.subcommand() {
+        Some(("list", _)) => list(),
+        Some(("show", show_m)) => return show(show_m.get_one::<String>("name").unwrap()),
+        _ => println!("No valid templates subcommand was used"),
+    }
+    Ok(())
+}
+
+fn list() {
+    let templates = list_templates();
+    if templates.is_empty() {
+        println!(
+            "No templates found. Add YAML files to .samvada/templates/ or ~/.samvada/templates/."
+        );
+        return;
+    }
+
+    for template in templates {
+        let description = load_template(&template.path)
+            .ok()
+            .and_then(|loaded| loaded.description)
+            .unwrap_or_default();
+        println!(
+            "{:<20} {:<8} {}",
+            template.name, template.origin, description
+        );
+    }
+}
+
+fn show(name: &str) -> Result<(), Error> {
+    let template = find_template(name)
+        .ok_or_else(|| Error::Config(format!("Template '{}' not found.", name)))?;
+    let content = fs::read_to_string(&template.path)
+        .map_err(|e| Error::io(format!("Failed to read {}", template.path.display()), e))?;
+    println!("# {}", template.path.display());
+    print!("{}", content);
+    Ok(())
+}
+
+/// Returns the template directories, project before global.
+fn template_dirs() -> Vec<(&'static str, PathBuf)> {
+    let mut dirs = Vec::new();
+
+    let global = get_config_dir()
+        .ok()
+        .map(|config_dir| config_dir.join(TEMPLATES_DIR_NAME));
+    let project = std::env::current_dir().ok().and_then(|cwd| {
+        cwd.ancestors()
+            .map(|dir| dir.join(".samvada").join(TEMPLATES_DIR_NAME))
+            .find(|candidate| candidate.is_dir())
+    });
+
+    if let Some(project) = project {
+        if global.as_ref() != Some(&project) {
+            dirs.push(("project", project));
+        }
+    }
+    if let Some(global) = global {
+        dirs.push(("global", global));
+    }
+    dirs
+}
+
+/// Lists every available template; a project template hides a global one of the same name.
+pub fn list_templates() -> Vec<TemplateFile> {
+    let mut templates: Vec<TemplateFile> = Vec::new();
+
+    for (origin, dir) in template_dirs() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut found: Vec<TemplateFile> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| is_template_file(path))
+            .filter_map(|path| {
+                let name = path.file_stem()?.to_str()?.to_string();
+                Some(TemplateFile { name, path, origin })
+            })
+            .filter(|found| !templates.iter().any(|known| known.name == found.name))
+            .collect();
+        found.sort_by(|a, b| a.name.cmp(&b.name));
+        templates.extend(found);
+    }
+
+    templates
+}
+
+/// Finds a template by name.
+pub fn find_template(name: &str) -> Option<TemplateFile> {
+    list_templates()
+        .into_iter()
+        .find(|template| template.name == name)
+}
+
+fn is_template_file(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .is_some_and(|extension| extension == "yaml" || extension == "yml")
+}
+
+/// Loads a template from a YAML file.
+pub fn load_template(path: &Path) -> Result<ChatTemplate, String> {
+    read_yaml_file(path).map_err(|e| format!("Failed to load {}: {}", path.display(), e))
+}
+
+/// Loads a template by name and fills its placeholders, returning the chat options it sets.
+pub fn render_template(name: &str, vars: &HashMap<String, String>) -> Result<ChatOptions, String> {
+    let file = find_template(name).ok_or_else(|| {
+        "template not found. Run `samvada chat templates list` to see what's available.".to_string()
+    })?;
+    let template = load_template(&file.path)?;
+
+    let mut strings: Vec<&str> = Vec::new();
+    strings.extend(template.system.as_deref());
+    strings.extend(template.model.as_deref());
+    strings.extend(template.tags.iter().map(String::as_str));
+    strings.extend(template.message.as_deref());
+    strings.extend(template.persona.as_deref());
+    for value in template.params.values() {
+        collect_param_strings(value, &mut |s| strings.push(s));
+    }
+
+    let values = resolve_placeholders(&placeholders(&strings), vars)?;
+    let fill = |text: &str| fill_placeholders(text, &values);
+
+    Ok(ChatOptions {
+        system: template.system.as_deref().map(fill),
+        model: template.model.as_deref().map(fill),
+        tags: template.tags.iter().map(|tag| fill(tag)).collect(),
+        params: template
+            .params
+            .iter()
+            .map(|(key, value)| (key.clone(), fill_param_strings(value.clone(), &fill)))
+            .collect(),
+        message: template.message.as_deref().map(fill),
+        persona: template.persona.as_deref().map(fill),
+        profile: None,
+    })
+}
+
+fn placeholder_regex() -> Regex {
+    Regex::new(r"\{\{\s*([A-Za-z_][\w-]*)\s*\}\}").expect("Failed to compile placeholder regex.")
+}
+
+/// Returns placeholder names in order of first appearance.
+fn placeholders(strings: &[&str]) -> Vec<String> {
+    let regex = placeholder_regex();
+    let mut names: Vec<String> = Vec::new();
+    for text in strings {
+        for cap in regex.captures_iter(text) {
+            if !names.iter().any(|name| name == &cap[1]) {
+                names.push(cap[1].to_string());
+            }
+        }
+    }
+    names
+}
+
+/// Takes each placeholder's value from `vars`, prompting for the rest on a terminal.
+fn resolve_placeholders(
+    names: &[String],
+    vars: &HashMap<String, String>,
+) -> Result<HashMap<String, String>, String> {
+    let mut values = HashMap::new();
+
+    for name in names {
+        let value = match vars.get(name) {
+            Some(value) => value.clone(),
+            None if atty::is(Stream::Stdin) => prompt(name).map_err(|e| e.to_string())?,
+            None => {
+                return Err(format!(
+                    "no value for {{{{{}}}}}. Pass it with --var {}=value.",
+                    name, name
+                ))
+            }
+        };
+        values.insert(name.clone(), value);
+    }
+
+    Ok(values)
+}
+
+fn prompt(name: &str) -> io::Result<String> {
+    eprint!("{}: ", name);
+    io::stderr().flush()?;
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn fill_placeholders(text: &str, values: &HashMap<String, String>) -> String {
+    placeholder_regex()
+        .replace_all(text, |cap: &regex::Captures| {
+            values.get(&cap[1]).cloned().unwrap_or_default()
+        })
+        .to_string()
+}
+
+fn collect_param_strings<'a>(value: &'a Value, push: &mut impl FnMut(&'a str)) {
+    match value {
+        Value::String(text) => push(text),
+        Value::Array(items) => items
+            .iter()
+            .for_each(|item| collect_param_strings(item, push)),
+        Value::Object(map) => map
+            .values()
+            .for_each(|item| collect_param_strings(item, push)),
+        _ => {}
+    }
+}
+
+fn fill_param_strings(value: Value, fill: &impl Fn(&str) -> String) -> Value {
+    match value {
+        Value::String(text) => Value::String(fill(&text)),
+        Value::Array(items) => Value::Array(
+            items
+                .into_iter()
+                .map(|item| fill_param_strings(item, fill))
+                .collect(),
+        ),
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, item)| (key, fill_param_strings(item, fill)))
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+/// Defines the 'templates' command for listing and showing chat templates.
+pub fn templates_command() -> Command {
+    Command::new("templates")
+        .about("List and show chat templates")
+        .subcommand(Command::new("list").about("List available templates"))
+        .subcommand(
+            Command::new("show").about("Show a template").arg(
+                Arg::new("name")
+                    .help("Name of the template")
+                    .required(true)
+                    .num_args(1),
+            ),
+        )
+}
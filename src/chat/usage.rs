@@ -0,0 +1,229 @@
+//! The usage ledger: one JSON line per answered request in `~/.samvada/usage.jsonl`.
+
+use chrono::{DateTime, FixedOffset, Local};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::chat::cache::CACHED_AT_FIELD;
+use crate::chat::cassette;
+use crate::chat::config::{find_project_config, get_config_dir, load_config_for, AppConfig};
+use crate::chat::parser::ParsedChat;
+
+/// Name of the ledger file in `~/.samvada`.
+const LEDGER_FILE_NAME: &str = "usage.jsonl";
+
+/// One answered request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageEntry {
+    /// When the answer was received, as an RFC 3339 timestamp.
+    pub date: String,
+    /// Absolute path of the chat file, if the answer was saved to one.
+    #[serde(default)]
+    pub chat: Option<String>,
+    /// Directory of the nearest `.samvada.yaml`, else the chat's directory.
+    #[serde(default)]
+    pub project: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// The profile the request was made with, if any.
+    #[serde(default)]
+    pub profile: Option<String>,
+    pub model: String,
+    pub endpoint: String,
+    /// The response's `id`, used to skip answers that were already recorded.
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub prompt_tokens: Option<u64>,
+    #[serde(default)]
+    pub completion_tokens: Option<u64>,
+    pub total_tokens: u64,
+    #[serde(default)]
+    pub latency_ms: Option<u64>,
+    /// Estimated cost in US dollars, when the model has a price.
+    #[serde(default)]
+    pub cost: Option<f64>,
+    /// Set for entries recovered from metadata comments by `usage backfill`.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub backfilled: bool,
+    /// Set when the token counts were estimated locally, as for a cancelled stream.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub estimated: bool,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+impl UsageEntry {
+    /// Builds the entry for an answer, pricing it with the config that applies to the chat.
+    pub fn from_response(
+        chat: &ParsedChat,
+        chat_file: Option<&Path>,
+        response_body: &Value,
+        latency: Duration,
+    ) -> UsageEntry {
+        let usage = &response_body["usage"];
+        let prompt_tokens = usage["prompt_tokens"].as_u64();
+        let completion_tokens = usage["completion_tokens"].as_u64();
+        let total_tokens = usage["total_tokens"]
+            .as_u64()
+            .unwrap_or(prompt_tokens.unwrap_or(0) + completion_tokens.unwrap_or(0));
+        // The response names the exact model version that answered
+        let model = response_body["model"]
+            .as_str()
+            .filter(|model| !model.is_empty())
+            .unwrap_or(&chat.model)
+            .to_string();
+
+        let start = chat_file.map_or_else(current_dir, Path::to_path_buf);
+        let config = load_config_for(&start).ok();
+        UsageEntry {
+            date: Local::now().to_rfc3339(),
+            chat: chat_file
+                .map(absolute)
+                .map(|path| path.display().to_string()),
+            project: project_dir(&start).map(|dir| dir.display().to_string()),
+            tags: chat.tags.clone(),
+            profile: chat.profile.clone(),
+            cost: config
+                .as_ref()
+                .and_then(|config| estimate_cost(config, &model, prompt_tokens, completion_tokens)),
+            model,
+            endpoint: chat.api_endpoint.clone(),
+            id: response_body["id"].as_str().map(str::to_string),
+            prompt_tokens,
+            completion_tokens,
+            total_tokens,
+            latency_ms: Some(latency.as_millis() as u64),
+            backfilled: false,
+            estimated: false,
+        }
+    }
+
+    /// The entry's date, if it is a valid timestamp.
+    pub fn timestamp(&self) -> Option<DateTime<FixedOffset>> {
+        DateTime::parse_from_rfc3339(&self.date).ok()
+    }
+}
+
+/// Estimates the cost of a request, when both token counts and the model's price are known.
+pub fn estimate_cost(
+    config: &AppConfig,
+    model: &str,
+    prompt_tokens: Option<u64>,
+    completion_tokens: Option<u64>,
+) -> Option<f64> {
+    Some(
+        config
+            .price_for(model)?
+            .cost(prompt_tokens?, completion_tokens?),
+    )
+}
+
+/// Returns the path of the ledger, `~/.samvada/usage.jsonl`.
+pub fn ledger_path() -> io::Result<PathBuf> {
+    Ok(get_config_dir()?.join(LEDGER_FILE_NAME))
+}
+
+/// Appends entries to the ledger.
+pub fn append(entries: &[UsageEntry]) -> io::Result<PathBuf> {
+    let path = ledger_path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().append(true).create(true).open(&path)?;
+    for entry in entries {
+        let line = serde_json::to_string(entry).map_err(io::Error::other)?;
+        writeln!(file, "{}", line)?;
+    }
+    Ok(path)
+}
+
+/// Reads every entry in the ledger, skipping lines that can't be parsed.
+pub fn load() -> io::Result<Vec<UsageEntry>> {
+    let path = ledger_path()?;
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    Ok(content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Records an answer in the ledger, warning instead of failing.
+///
+/// Replayed and cached answers cost nothing and are not recorded.
+pub fn record(
+    chat: &ParsedChat,
+    chat_file: Option<&Path>,
+    response_body: &Value,
+    latency: Duration,
+) {
+    if cassette::replaying() || response_body.get(CACHED_AT_FIELD).is_some() {
+        return;
+    }
+    let entry = UsageEntry::from_response(chat, chat_file, response_body, latency);
+    append_or_warn(entry);
+}
+
+/// Records a request whose answer never arrived in full, with locally estimated token counts.
+pub fn record_estimated(
+    chat: &ParsedChat,
+    chat_file: Option<&Path>,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    latency: Duration,
+) {
+    if cassette::replaying() {
+        return;
+    }
+    let response_body = json!({
+        "usage": {
+            "prompt_tokens": prompt_tokens,
+            "completion_tokens": completion_tokens,
+            "total_tokens": prompt_tokens + completion_tokens,
+        },
+    });
+    let mut entry = UsageEntry::from_response(chat, chat_file, &response_body, latency);
+    entry.estimated = true;
+    append_or_warn(entry);
+}
+
+fn append_or_warn(entry: UsageEntry) {
+    if let Err(e) = append(&[entry]) {
+        log::warn!("Failed to record usage: {}", e);
+        eprintln!("Warning: failed to record usage: {}", e);
+    }
+}
+
+/// The directory of the nearest `.samvada.yaml` above `start`, else `start`'s own directory.
+pub fn project_dir(start: &Path) -> Option<PathBuf> {
+    if let Some(config) = find_project_config(start) {
+        return config.parent().map(Path::to_path_buf);
+    }
+    let start = absolute(start);
+    if start.is_dir() {
+        Some(start)
+    } else {
+        start.parent().map(Path::to_path_buf)
+    }
+}
+
+/// `path` made absolute with symlinks resolved, or unchanged if it doesn't exist.
+pub(crate) fn absolute(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// The working directory, else `.`.
+pub(crate) fn current_dir() -> PathBuf {
+    std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."))
+}
@@ -0,0 +1,422 @@
+//! Encrypted vault of API keys, an opt-in alternative to the plain-text
+//! credential store.
+//!
+//! The vault holds the same data as `~/.samvada/credentials`, encrypted with
+//! ChaCha20-Poly1305 under a key derived from a passphrase with Argon2id.
+//! `samvada auth vault unlock` hands the derived key to a small agent process
+//! that serves it over a Unix socket until it expires, so the passphrase is
+//! not asked for on every request.
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use atty::Stream;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use crate::chat::config::get_config_dir;
+use crate::chat::credentials::{write_private_file, Credentials};
+
+/// Name of the vault file in `~/.samvada`.
+const VAULT_FILE_NAME: &str = "vault";
+
+/// Directory in `~/.samvada`, only accessible by the current user, that holds the agent's socket.
+const AGENT_DIR_NAME: &str = "agent";
+
+/// Name of the agent's socket in `AGENT_DIR_NAME`.
+const AGENT_SOCKET_NAME: &str = "vault.sock";
+
+/// Environment variable that can hold the passphrase, e.g. for CI.
+pub const PASSPHRASE_VAR: &str = "SAMVADA_VAULT_PASSPHRASE";
+
+const VAULT_VERSION: u32 = 1;
+const KEY_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+
+/// The vault file: key derivation settings and the encrypted credentials.
+#[derive(Debug, Deserialize, Serialize)]
+struct VaultFile {
+    version: u32,
+    kdf: Kdf,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Argon2id settings used to derive the encryption key from the passphrase.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct Kdf {
+    algorithm: String,
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+    salt: String,
+}
+
+impl Kdf {
+    fn new() -> Kdf {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        Kdf {
+            algorithm: "argon2id".to_string(),
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+            salt: to_hex(&salt),
+        }
+    }
+
+    fn derive_key(&self, passphrase: &str) -> io::Result<[u8; KEY_LEN]> {
+        if self.algorithm != "argon2id" {
+            return Err(invalid_data(format!(
+                "unsupported key derivation '{}'",
+                self.algorithm
+            )));
+        }
+        let params = Params::new(
+            self.memory_kib,
+            self.iterations,
+            self.parallelism,
+            Some(KEY_LEN),
+        )
+        .map_err(|e| invalid_data(e.to_string()))?;
+        let salt = from_hex(&self.salt)?;
+        let mut key = [0u8; KEY_LEN];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+            .map_err(|e| invalid_data(e.to_string()))?;
+        Ok(key)
+    }
+}
+
+/// An unlocked vault.
+pub struct Vault {
+    pub credentials: Credentials,
+    kdf: Kdf,
+    key: [u8; KEY_LEN],
+}
+
+/// Returns the path of the vault file.
+pub fn vault_path() -> io::Result<PathBuf> {
+    Ok(get_config_dir()?.join(VAULT_FILE_NAME))
+}
+
+/// Returns the path of the agent's socket.
+pub fn agent_socket_path() -> io::Result<PathBuf> {
+    Ok(get_config_dir()?
+        .join(AGENT_DIR_NAME)
+        .join(AGENT_SOCKET_NAME))
+}
+
+/// Whether a vault has been created; without one the vault is not used at all.
+pub fn vault_exists() -> bool {
+    vault_path().map(|path| path.exists()).unwrap_or(false)
+}
+
+impl Vault {
+    /// Creates a new vault holding `credentials`, encrypted with `passphrase`.
+    pub fn create(passphrase: &str, credentials: Credentials) -> io::Result<Vault> {
+        let kdf = Kdf::new();
+        let key = kdf.derive_key(passphrase)?;
+        let vault = Vault {
+            credentials,
+            kdf,
+            key,
+        };
+        vault.save()?;
+        Ok(vault)
+    }
+
+    /// Opens the vault with its passphrase.
+    pub fn unlock(passphrase: &str) -> io::Result<Vault> {
+        let file = read_vault_file()?;
+        let key = file.kdf.derive_key(passphrase)?;
+        decrypt(file, key)
+    }
+
+    /// Opens the vault with the key cached by the agent, or asks for the
+    /// passphrase when `prompt` is set. Returns `None` if there is no vault.
+    pub fn open(prompt: bool) -> io::Result<Option<Vault>> {
+        if !vault_exists() {
+            return Ok(None);
+        }
+        if let Some(key) = agent_key() {
+            // A key cached before the passphrase was rotated no longer fits
+            if let Ok(vault) = decrypt(read_vault_file()?, key) {
+                return Ok(Some(vault));
+            }
+        }
+        if !prompt {
+            return Err(locked());
+        }
+        let passphrase = read_passphrase("Vault passphrase: ")?.ok_or_else(locked)?;
+        Vault::unlock(&passphrase).map(Some)
+    }
+
+    /// The derived key, as cached by the agent.
+    pub fn key(&self) -> [u8; KEY_LEN] {
+        self.key
+    }
+
+    /// Encrypts the credentials with a fresh nonce and writes the vault file,
+    /// readable and writable only by the current user.
+    pub fn save(&self) -> io::Result<PathBuf> {
+        let plaintext = serde_json::to_vec(&self.credentials)?;
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&self.key));
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext.as_slice())
+            .map_err(|_| invalid_data("failed to encrypt the vault".to_string()))?;
+        let file = VaultFile {
+            version: VAULT_VERSION,
+            kdf: self.kdf.clone(),
+            nonce: to_hex(&nonce),
+            ciphertext: to_hex(&ciphertext),
+        };
+
+        let path = vault_path()?;
+        let mut content = serde_json::to_string_pretty(&file)?;
+        content.push('\n');
+        write_private_file(&path, content.as_bytes())?;
+        Ok(path)
+    }
+
+    /// Re-encrypts the vault under a new passphrase, with a new salt.
+    pub fn rotate(&mut self, passphrase: &str) -> io::Result<PathBuf> {
+        let kdf = Kdf::new();
+        self.key = kdf.derive_key(passphrase)?;
+        self.kdf = kdf;
+        self.save()
+    }
+}
+
+fn read_vault_file() -> io::Result<VaultFile> {
+    let path = vault_path()?;
+    let content = fs::read_to_string(&path)?;
+    let file: VaultFile = serde_json::from_str(&content)
+        .map_err(|e| invalid_data(format!("{} is not a valid vault: {}", path.display(), e)))?;
+    if file.version != VAULT_VERSION {
+        return Err(invalid_data(format!(
+            "{} has unsupported version {}",
+            path.display(),
+            file.version
+        )));
+    }
+    Ok(file)
+}
+
+fn decrypt(file: VaultFile, key: [u8; KEY_LEN]) -> io::Result<Vault> {
+    let nonce = from_hex(&file.nonce)?;
+    if nonce.len() != 12 {
+        return Err(invalid_data("the vault's nonce is corrupt".to_string()));
+    }
+    let ciphertext = from_hex(&file.ciphertext)?;
+    let plaintext = ChaCha20Poly1305::new(Key::from_slice(&key))
+        .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+        .map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "wrong passphrase, or the vault is corrupt",
+            )
+        })?;
+    let credentials = serde_json::from_slice(&plaintext)
+        .map_err(|e| invalid_data(format!("the vault's contents are invalid: {}", e)))?;
+    Ok(Vault {
+        credentials,
+        kdf: file.kdf,
+        key,
+    })
+}
+
+/// Reads a passphrase from `SAMVADA_VAULT_PASSPHRASE`, else from the terminal.
+/// Returns `None` when neither is available.
+pub fn read_passphrase(prompt: &str) -> io::Result<Option<String>> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_VAR) {
+        return Ok(Some(passphrase));
+    }
+    if !atty::is(Stream::Stdin) {
+        return Ok(None);
+    }
+    rpassword::prompt_password(prompt).map(Some)
+}
+
+fn locked() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        "the vault is locked; run `samvada auth vault unlock`",
+    )
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> io::Result<Vec<u8>> {
+    let hex = hex.trim();
+    if !hex.len().is_multiple_of(2) {
+        return Err(invalid_data("invalid hex in the vault".to_string()));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .map_err(|_| invalid_data("invalid hex in the vault".to_string()))
+        })
+        .collect()
+}
+
+fn key_from_hex(hex: &str) -> Option<[u8; KEY_LEN]> {
+    from_hex(hex).ok()?.try_into().ok()
+}
+
+/// Asks a running agent for the cached key.
+#[cfg(unix)]
+pub fn agent_key() -> Option<[u8; KEY_LEN]> {
+    key_from_hex(&agent_request("key")?)
+}
+
+#[cfg(not(unix))]
+pub fn agent_key() -> Option<[u8; KEY_LEN]> {
+    None
+}
+
+/// Whether an agent is holding the key.
+pub fn agent_running() -> bool {
+    agent_key().is_some()
+}
+
+/// Sends one command to the agent and returns its reply.
+#[cfg(unix)]
+fn agent_request(command: &str) -> Option<String> {
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::net::UnixStream;
+    use std::time::Duration;
+
+    let mut stream = UnixStream::connect(agent_socket_path().ok()?).ok()?;
+    stream.set_read_timeout(Some(Duration::from_secs(2))).ok()?;
+    writeln!(stream, "{}", command).ok()?;
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply).ok()?;
+    Some(reply.trim().to_string())
+}
+
+/// Starts an agent that caches `key` for `timeout`, replacing a running one.
+#[cfg(unix)]
+pub fn start_agent(key: [u8; KEY_LEN], timeout: std::time::Duration) -> io::Result<()> {
+    use std::os::unix::process::CommandExt;
+    use std::process::{Command, Stdio};
+
+    stop_agent();
+    let mut child = Command::new(std::env::current_exe()?)
+        .args(["auth", "vault", "agent", "--timeout"])
+        .arg(timeout.as_secs().to_string())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        // Keep Ctrl-C in this terminal from reaching the agent
+        .process_group(0)
+        .spawn()?;
+    // The key goes over a pipe so it never shows up in the process list
+    let mut stdin = child.stdin.take().expect("agent stdin is piped");
+    writeln!(stdin, "{}", to_hex(&key))?;
+    drop(stdin);
+
+    for _ in 0..50 {
+        if agent_key() == Some(key) {
+            return Ok(());
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    Err(io::Error::new(
+        io::ErrorKind::TimedOut,
+        "the vault agent did not start",
+    ))
+}
+
+#[cfg(not(unix))]
+pub fn start_agent(_key: [u8; KEY_LEN], _timeout: std::time::Duration) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "caching the unlocked vault needs Unix sockets",
+    ))
+}
+
+/// Stops a running agent, forgetting the cached key. Returns whether one was running.
+#[cfg(unix)]
+pub fn stop_agent() -> bool {
+    agent_request("lock").is_some()
+}
+
+#[cfg(not(unix))]
+pub fn stop_agent() -> bool {
+    false
+}
+
+/// Runs the agent: reads the key from stdin and serves it on the socket
+/// until `timeout` passes or it is told to lock.
+#[cfg(unix)]
+pub fn serve_agent(timeout: std::time::Duration) -> io::Result<()> {
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+    use std::os::unix::net::UnixListener;
+
+    let mut line = String::new();
+    io::stdin().read_line(&mut line)?;
+    let key = key_from_hex(&line).ok_or_else(|| invalid_data("invalid key".to_string()))?;
+
+    let socket = agent_socket_path()?;
+    // Only the current user may enter the directory, so nobody else can
+    // connect to the socket, even before its own permissions are set
+    let dir = socket
+        .parent()
+        .expect("the socket is in the agent directory");
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)?;
+    fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+    let _ = fs::remove_file(&socket);
+    let listener = UnixListener::bind(&socket)?;
+    fs::set_permissions(&socket, fs::Permissions::from_mode(0o600))?;
+
+    let expired = socket.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(timeout);
+        let _ = fs::remove_file(&expired);
+        std::process::exit(0);
+    });
+
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else { continue };
+        let mut command = String::new();
+        if BufReader::new(&stream).read_line(&mut command).is_err() {
+            continue;
+        }
+        match command.trim() {
+            "key" => {
+                let _ = writeln!(stream, "{}", to_hex(&key));
+            }
+            "lock" => {
+                let _ = writeln!(stream, "ok");
+                let _ = fs::remove_file(&socket);
+                return Ok(());
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn serve_agent(_timeout: std::time::Duration) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "the vault agent needs Unix sockets",
+    ))
+}
@@ -0,0 +1,147 @@
+//! The error type shared by every command, and the exit codes it maps to.
+//!
+//! | Code | Meaning                                                    |
+//! |------|------------------------------------------------------------|
+//! | 0    | Success                                                    |
+//! | 1    | A check failed (`lint` found errors, `fmt --check`)        |
+//! | 2    | Invalid command-line usage                                 |
+//! | 3    | Configuration error                                        |
+//! | 4    | No usable API key, or the vault could not be opened        |
+//! | 5    | The chat file (or another input) could not be parsed       |
+//! | 6    | A `[[file]]` reference could not be read                   |
+//! | 7    | Network error: the endpoint could not be reached           |
+//! | 8    | The API answered with an error status                      |
+//! | 9    | The API rate limited the request (HTTP 429)                |
+//! | 10   | Reading or writing a file failed                           |
+//! | 11   | A budget cap was reached (see `--override-budget`)         |
+//! | 12   | A replayed request is not in the cassette                  |
+
+use std::fmt;
+use std::io;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// A check found problems; they have already been reported.
+    CheckFailed,
+    Usage(String),
+    Config(String),
+    Auth(String),
+    Parse(String),
+    Reference {
+        path: String,
+        source: io::Error,
+    },
+    Network(String),
+    Api {
+        status: u16,
+        message: String,
+    },
+    Io {
+        context: String,
+        source: io::Error,
+    },
+    Budget(String),
+    /// `--replay` found no recorded response for a request.
+    CassetteMiss(String),
+}
+
+impl Error {
+    /// The process exit code for this error, see the table above.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::CheckFailed => 1,
+            Error::Usage(_) => 2,
+            Error::Config(_) => 3,
+            Error::Auth(_) => 4,
+            Error::Parse(_) => 5,
+            Error::Reference { .. } => 6,
+            Error::Network(_) => 7,
+            Error::Api { status: 429, .. } => 9,
+            Error::Api { .. } => 8,
+            Error::Io { .. } => 10,
+            Error::Budget(_) => 11,
+            Error::CassetteMiss(_) => 12,
+        }
+    }
+
+    /// Wraps an IO error with what was being done, e.g. "Failed to write chat.md".
+    pub fn io(context: impl Into<String>, source: io::Error) -> Error {
+        Error::Io {
+            context: context.into(),
+            source,
+        }
+    }
+
+    /// Says what was being done when an IO error happened; other errors are kept as they are.
+    pub fn context(self, context: impl Into<String>) -> Error {
+        match self {
+            Error::Io {
+                context: inner,
+                source,
+            } if !inner.is_empty() => Error::io(format!("{}: {}", context.into(), inner), source),
+            Error::Io { source, .. } => Error::io(context, source),
+            e => e,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::CheckFailed => write!(f, "check failed"),
+            Error::Usage(message)
+            | Error::Config(message)
+            | Error::Auth(message)
+            | Error::Parse(message)
+            | Error::Network(message)
+            | Error::Budget(message)
+            | Error::CassetteMiss(message) => write!(f, "{}", message),
+            Error::Reference { path, source } => {
+                write!(f, "Failed to read referenced file {}: {}", path, source)
+            }
+            Error::Api { status, message } if *status == 429 => {
+                write!(f, "Rate limited by the API (status 429): {}", message)
+            }
+            Error::Api { status, message } => {
+                write!(f, "API error (status {}): {}", status, message)
+            }
+            Error::Io { context, source } if context.is_empty() => write!(f, "{}", source),
+            Error::Io { context, source } => write!(f, "{}: {}", context, source),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Reference { source, .. } | Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(source: io::Error) -> Error {
+        Error::io("", source)
+    }
+}
+
+impl From<config::ConfigError> for Error {
+    fn from(e: config::ConfigError) -> Error {
+        Error::Config(format!("Error loading config: {}", e))
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Error {
+        match e.status() {
+            Some(status) => Error::Api {
+                status: status.as_u16(),
+                message: e.to_string(),
+            },
+            None => Error::Network(format!("Request failed: {}", e)),
+        }
+    }
+}
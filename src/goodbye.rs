@@ -1,6 +1,8 @@
 use clap::{Arg, ArgMatches, Command};
 
-pub fn handle_goodbye_subcommand(matches: &ArgMatches) {
+use crate::error::Error;
+
+pub fn handle_goodbye_subcommand(matches: &ArgMatches) -> Result<(), Error> {
     let names = get_names(matches);
     let caps = matches.get_flag("caps");
     let surname = matches.get_one::<String>("surname").map(String::as_str);
@@ -8,12 +10,14 @@ pub fn handle_goodbye_subcommand(matches: &ArgMatches) {
 
     if let Some(date) = date_after {
         if !validate_date_format(date) {
-            eprintln!("Error: Date must be in yyyy-mm-dd format.");
-            std::process::exit(1);
+            return Err(Error::Usage(
+                "Date must be in yyyy-mm-dd format.".to_string(),
+            ));
         }
     }
 
     generate_goodbyes(names, caps, surname, date_after);
+    Ok(())
 }
 
 pub fn generate_goodbyes(
@@ -1,32 +1,135 @@
+mod auth;
+mod cache;
 mod chat;
+mod error;
 mod goodbye;
 mod greeting;
+mod mock_server;
+mod settings;
+mod usage;
 
-use clap::{ArgMatches, Command};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::path::PathBuf;
 
 #[tokio::main]
 async fn main() {
     let matches = parse_arguments();
+    if let Some(profile) = matches.get_one::<String>("profile") {
+        chat::config::set_profile_flag(profile);
+    }
+    chat::logging::set_log_flags(chat::logging::LogFlags {
+        verbosity: if matches.get_flag("quiet") {
+            -1
+        } else {
+            matches.get_count("verbose") as i8
+        },
+        log_file: matches.get_one::<String>("log-file").map(PathBuf::from),
+        stderr: matches.get_flag("log-stderr"),
+    });
+    if let Err(e) = chat::cassette::init(
+        matches.get_one::<String>("record").map(String::as_str),
+        matches.get_one::<String>("replay").map(String::as_str),
+    ) {
+        exit_with(e);
+    }
 
-    match matches.subcommand() {
+    let result = match matches.subcommand() {
         Some(("greeting", sub_m)) => {
             greeting::handle_greeting_subcommand(sub_m);
+            Ok(())
         }
-        Some(("goodbye", sub_m)) => {
-            goodbye::handle_goodbye_subcommand(sub_m);
+        Some(("goodbye", sub_m)) => goodbye::handle_goodbye_subcommand(sub_m),
+        Some(("chat", sub_m)) => chat::handle_chat_subcommand(sub_m).await,
+        Some(("auth", sub_m)) => auth::handle_auth_subcommand(sub_m),
+        Some(("config", sub_m)) => settings::handle_config_subcommand(sub_m),
+        Some(("usage", sub_m)) => usage::handle_usage_subcommand(sub_m),
+        Some(("cache", sub_m)) => cache::handle_cache_subcommand(sub_m),
+        Some(("mock-server", sub_m)) => mock_server::handle_mock_server_subcommand(sub_m).await,
+        _ => {
+            println!("No valid subcommand was used");
+            Ok(())
         }
-        Some(("chat", sub_m)) => {
-            chat::handle_chat_subcommand(sub_m).await;
-        }
-        _ => println!("No valid subcommand was used"),
+    };
+
+    if let Err(e) = result {
+        exit_with(e);
+    }
+}
+
+/// Reports the error and exits with its exit code.
+fn exit_with(e: error::Error) -> ! {
+    // Failed checks have already reported their problems
+    if !matches!(e, error::Error::CheckFailed) {
+        eprintln!("Error: {}", e);
     }
+    std::process::exit(e.exit_code());
 }
 
 fn parse_arguments() -> ArgMatches {
     Command::new("hello_world")
         .about("A simple greeting program")
+        .arg(
+            Arg::new("profile")
+                .long("profile")
+                .help("Config profile to use, overriding SAMVADA_PROFILE and the frontmatter")
+                .global(true)
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .help("Log more detail: -v for debug messages, -vv for everything")
+                .global(true)
+                .action(ArgAction::Count),
+        )
+        .arg(
+            Arg::new("quiet")
+                .short('q')
+                .long("quiet")
+                .help("Only log warnings and errors")
+                .global(true)
+                .conflicts_with("verbose")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("log-file")
+                .long("log-file")
+                .help("Write the log to this file instead of next to the chat")
+                .global(true)
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("log-stderr")
+                .long("log-stderr")
+                .help("Also print log messages to stderr")
+                .global(true)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("record")
+                .long("record")
+                .value_name("CASSETTE")
+                .help("Save each API response to this cassette file (or set SAMVADA_RECORD)")
+                .global(true)
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("replay")
+                .long("replay")
+                .value_name("CASSETTE")
+                .help("Answer from this cassette file instead of the API (or set SAMVADA_REPLAY)")
+                .global(true)
+                .conflicts_with("record")
+                .num_args(1),
+        )
         .subcommand(greeting::greeting_command())
         .subcommand(goodbye::goodbye_command())
         .subcommand(chat::chat_command())
+        .subcommand(settings::config_command())
+        .subcommand(auth::auth_command())
+        .subcommand(usage::usage_command())
+        .subcommand(cache::cache_command())
+        .subcommand(mock_server::mock_server_command())
         .get_matches()
 }
@@ -0,0 +1,484 @@
+//! `samvada mock-server`: a small OpenAI-compatible server for demos and offline development.
+//!
+//! It speaks just enough HTTP/1.1 for API clients, closing the connection
+//! after each response.
+
+mod script;
+
+use chrono::Utc;
+use clap::{Arg, ArgMatches, Command};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::chat::dry_run::{estimate_text_tokens, estimate_tokens};
+use crate::error::{Error, Result};
+use script::{Answer, Script, DEFAULT_MODEL};
+
+/// Largest request body the server reads.
+const MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+
+/// Pause between streamed chunks, so clients can be seen rendering them.
+const CHUNK_INTERVAL: Duration = Duration::from_millis(30);
+
+/// Length of the vectors returned by `/v1/embeddings` unless `dimensions` is given.
+const DEFAULT_DIMENSIONS: usize = 16;
+
+/// Errors injected into every `fail_every`th request, and a delay before every answer.
+struct Faults {
+    status: Option<u16>,
+    every: u64,
+    delay: Option<Duration>,
+}
+
+struct Server {
+    script: Script,
+    faults: Faults,
+    requests: AtomicU64,
+}
+
+/// A parsed HTTP request.
+struct Request {
+    method: String,
+    path: String,
+    /// The `Content-Length` the client declared; the body isn't read when it is over the limit.
+    content_length: usize,
+    body: Vec<u8>,
+}
+
+/// A response, either complete or streamed as server-sent events.
+enum Response {
+    Json(u16, Value),
+    Events(Vec<Value>),
+}
+
+/// Handles the mock-server subcommand: serves until interrupted.
+pub async fn handle_mock_server_subcommand(matches: &ArgMatches) -> Result<()> {
+    let host = matches.get_one::<String>("host").unwrap();
+    let port = *matches.get_one::<u16>("port").unwrap();
+    let script = match matches.get_one::<String>("script") {
+        Some(path) => Script::load(Path::new(path))?,
+        None => Script::default(),
+    };
+    let server = Arc::new(Server {
+        script,
+        faults: Faults {
+            status: matches.get_one::<u16>("fail").copied(),
+            every: *matches.get_one::<u64>("fail-every").unwrap(),
+            delay: matches
+                .get_one::<u64>("delay")
+                .map(|ms| Duration::from_millis(*ms)),
+        },
+        requests: AtomicU64::new(0),
+    });
+
+    let listener = TcpListener::bind((host.as_str(), port))
+        .await
+        .map_err(|e| Error::io(format!("Failed to listen on {}:{}", host, port), e))?;
+    let address = listener.local_addr()?;
+    println!("Mock OpenAI server listening on http://{}", address);
+    println!("Set api_endpoint to http://{}/v1/chat/completions", address);
+
+    loop {
+        let (stream, _) = listener
+            .accept()
+            .await
+            .map_err(|e| Error::io("Failed to accept a connection", e))?;
+        let server = Arc::clone(&server);
+        tokio::spawn(async move {
+            if let Err(e) = server.serve(stream).await {
+                eprintln!("Warning: {}", e);
+            }
+        });
+    }
+}
+
+impl Server {
+    async fn serve(&self, mut stream: TcpStream) -> Result<()> {
+        let started = Instant::now();
+        let Some(request) = read_request(&mut stream).await? else {
+            return Ok(());
+        };
+        let (response, delay) = self.respond(&request);
+        if let Some(delay) = delay {
+            tokio::time::sleep(delay).await;
+        }
+
+        let status = match &response {
+            Response::Json(status, _) => *status,
+            Response::Events(_) => 200,
+        };
+        write_response(&mut stream, response).await?;
+        println!(
+            "{} {} {} ({} ms)",
+            request.method,
+            request.path,
+            status,
+            started.elapsed().as_millis()
+        );
+        Ok(())
+    }
+
+    /// Routes a request, returning the response and how long to wait before sending it.
+    fn respond(&self, request: &Request) -> (Response, Option<Duration>) {
+        let path = request.path.split('?').next().unwrap_or_default();
+        let route = ["/chat/completions", "/embeddings", "/models"]
+            .into_iter()
+            .find(|route| path.ends_with(route));
+        let expected_method = if route == Some("/models") {
+            "GET"
+        } else {
+            "POST"
+        };
+        match route {
+            None => return (error(404, &format!("Unknown path {}", path)), None),
+            Some(_) if request.method != expected_method => {
+                let message = format!("{} is not allowed on {}", request.method, path);
+                return (error(405, &message), None);
+            }
+            Some("/models") => return (self.models(), None),
+            Some(_) if request.content_length > MAX_BODY_SIZE => {
+                let message = format!(
+                    "Request body of {} bytes is over the {} byte limit",
+                    request.content_length, MAX_BODY_SIZE
+                );
+                return (error(413, &message), None);
+            }
+            Some(_) => {}
+        }
+
+        let count = self.requests.fetch_add(1, Ordering::Relaxed) + 1;
+        let delay = self.faults.delay;
+        if let Some(status) = self.faults.status {
+            if count.is_multiple_of(self.faults.every.max(1)) {
+                return (error(status, "Injected error"), delay);
+            }
+        }
+
+        let body: Value = match serde_json::from_slice(&request.body) {
+            Ok(body) => body,
+            Err(e) => return (error(400, &format!("Invalid JSON body: {}", e)), delay),
+        };
+        match route {
+            Some("/embeddings") => (embeddings(&body), delay),
+            _ => {
+                let (response, rule_delay) = self.chat_completion(&body, count);
+                (response, rule_delay.or(delay))
+            }
+        }
+    }
+
+    fn models(&self) -> Response {
+        let models = if self.script.models.is_empty() {
+            vec![DEFAULT_MODEL.to_string()]
+        } else {
+            self.script.models.clone()
+        };
+        let data: Vec<Value> = models
+            .iter()
+            .map(|id| json!({"id": id, "object": "model", "created": 0, "owned_by": "samvada"}))
+            .collect();
+        Response::Json(200, json!({"object": "list", "data": data}))
+    }
+
+    fn chat_completion(&self, body: &Value, count: u64) -> (Response, Option<Duration>) {
+        let messages = body["messages"].as_array().cloned().unwrap_or_default();
+        let question = messages
+            .iter()
+            .rev()
+            .filter(|message| message["role"] == "user")
+            .filter_map(|message| message["content"].as_str())
+            .find(|content| !content.trim().is_empty())
+            .unwrap_or_default();
+        let model = body["model"].as_str().unwrap_or(DEFAULT_MODEL);
+        let id = format!("chatcmpl-mock-{}", count);
+
+        let (answer, delay) = self.script.answer(question);
+        let response = match answer {
+            Answer::Echo => completion(&id, model, &messages, question),
+            Answer::Text(text) => completion(&id, model, &messages, &text),
+            Answer::Body(body) => body,
+            Answer::Error(status) => return (error(status, "Scripted error"), delay),
+        };
+        if body["stream"].as_bool() != Some(true) {
+            return (Response::Json(200, response), delay);
+        }
+
+        let include_usage = body["stream_options"]["include_usage"].as_bool() == Some(true);
+        (
+            Response::Events(stream_chunks(&response, include_usage)),
+            delay,
+        )
+    }
+}
+
+/// Builds a `chat.completion` response answering with `text`.
+fn completion(id: &str, model: &str, messages: &[Value], text: &str) -> Value {
+    let prompt_tokens = estimate_tokens(messages);
+    let completion_tokens = estimate_text_tokens(text);
+    json!({
+        "id": id,
+        "object": "chat.completion",
+        "created": Utc::now().timestamp(),
+        "model": model,
+        "choices": [{
+            "index": 0,
+            "message": {"role": "assistant", "content": text},
+            "finish_reason": "stop",
+        }],
+        "usage": {
+            "prompt_tokens": prompt_tokens,
+            "completion_tokens": completion_tokens,
+            "total_tokens": prompt_tokens + completion_tokens,
+        },
+    })
+}
+
+/// Splits a completion into `chat.completion.chunk` events, one per word.
+fn stream_chunks(response: &Value, include_usage: bool) -> Vec<Value> {
+    let chunk = |delta: Value, finish_reason: Value| {
+        json!({
+            "id": response["id"],
+            "object": "chat.completion.chunk",
+            "created": response["created"],
+            "model": response["model"],
+            "choices": [{"index": 0, "delta": delta, "finish_reason": finish_reason}],
+        })
+    };
+    let text = response["choices"][0]["message"]["content"]
+        .as_str()
+        .unwrap_or_default();
+
+    let mut chunks = vec![chunk(
+        json!({"role": "assistant", "content": ""}),
+        Value::Null,
+    )];
+    chunks.extend(
+        text.split_inclusive(' ')
+            .map(|word| chunk(json!({ "content": word }), Value::Null)),
+    );
+    let finish_reason = response["choices"][0]["finish_reason"].clone();
+    chunks.push(chunk(json!({}), finish_reason));
+    if include_usage {
+        let mut usage = chunk(Value::Null, Value::Null);
+        usage["choices"] = json!([]);
+        usage["usage"] = response["usage"].clone();
+        chunks.push(usage);
+    }
+    chunks
+}
+
+/// Answers `/v1/embeddings` with vectors derived from a hash of each input, so equal inputs get equal vectors.
+fn embeddings(body: &Value) -> Response {
+    let inputs: Vec<String> = match &body["input"] {
+        Value::String(input) => vec![input.clone()],
+        Value::Array(inputs) => inputs
+            .iter()
+            .map(|input| {
+                input
+                    .as_str()
+                    .map_or_else(|| input.to_string(), str::to_string)
+            })
+            .collect(),
+        _ => return error(400, "'input' must be a string or an array"),
+    };
+    let dimensions = body["dimensions"]
+        .as_u64()
+        .map_or(DEFAULT_DIMENSIONS, |dimensions| dimensions as usize);
+
+    let data: Vec<Value> = inputs
+        .iter()
+        .enumerate()
+        .map(|(index, input)| {
+            json!({"object": "embedding", "index": index, "embedding": embed(input, dimensions)})
+        })
+        .collect();
+    let tokens: u64 = inputs.iter().map(|input| estimate_text_tokens(input)).sum();
+    Response::Json(
+        200,
+        json!({
+            "object": "list",
+            "data": data,
+            "model": body["model"].as_str().unwrap_or(DEFAULT_MODEL),
+            "usage": {"prompt_tokens": tokens, "total_tokens": tokens},
+        }),
+    )
+}
+
+/// A unit-length vector of `dimensions` values between -1 and 1, seeded by the input.
+fn embed(input: &str, dimensions: usize) -> Vec<f64> {
+    let mut values = Vec::with_capacity(dimensions);
+    let mut seed = Sha256::digest(input.as_bytes()).to_vec();
+    while values.len() < dimensions {
+        values.extend(
+            seed.iter()
+                .take(dimensions - values.len())
+                .map(|byte| *byte as f64 / 127.5 - 1.0),
+        );
+        seed = Sha256::digest(&seed).to_vec();
+    }
+    let norm = values.iter().map(|value| value * value).sum::<f64>().sqrt();
+    if norm > 0.0 {
+        values.iter_mut().for_each(|value| *value /= norm);
+    }
+    values
+}
+
+/// An OpenAI-style `{"error": {...}}` response.
+fn error(status: u16, message: &str) -> Response {
+    let kind = match status {
+        400 | 404 | 405 | 413 => "invalid_request_error",
+        429 => "rate_limit_exceeded",
+        _ => "server_error",
+    };
+    Response::Json(
+        status,
+        json!({"error": {"message": message, "type": kind, "code": status}}),
+    )
+}
+
+/// Reads one request, or `None` if the client disconnected without sending one.
+async fn read_request(stream: &mut TcpStream) -> Result<Option<Request>> {
+    let failed = |e| Error::io("Failed to read a request", e);
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    if reader.read_line(&mut line).await.map_err(failed)? == 0 {
+        return Ok(None);
+    }
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or("/").to_string();
+
+    let mut content_length = 0;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await.map_err(failed)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+
+    let mut body = Vec::new();
+    if content_length <= MAX_BODY_SIZE {
+        body.resize(content_length, 0);
+        reader.read_exact(&mut body).await.map_err(failed)?;
+    }
+    Ok(Some(Request {
+        method,
+        path,
+        content_length,
+        body,
+    }))
+}
+
+async fn write_response(stream: &mut TcpStream, response: Response) -> Result<()> {
+    let failed = |e| Error::io("Failed to send a response", e);
+    match response {
+        Response::Json(status, body) => {
+            let body = body.to_string();
+            let retry_after = if status == 429 {
+                "Retry-After: 1\r\n"
+            } else {
+                ""
+            };
+            let head = format!(
+                "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n",
+                status,
+                reason(status),
+                body.len(),
+                retry_after
+            );
+            stream.write_all(head.as_bytes()).await.map_err(failed)?;
+            stream.write_all(body.as_bytes()).await.map_err(failed)?;
+        }
+        Response::Events(chunks) => {
+            let head = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n";
+            stream.write_all(head.as_bytes()).await.map_err(failed)?;
+            for chunk in chunks {
+                let event = format!("data: {}\n\n", chunk);
+                stream.write_all(event.as_bytes()).await.map_err(failed)?;
+                stream.flush().await.map_err(failed)?;
+                tokio::time::sleep(CHUNK_INTERVAL).await;
+            }
+            stream
+                .write_all(b"data: [DONE]\n\n")
+                .await
+                .map_err(failed)?;
+        }
+    }
+    stream.shutdown().await.map_err(failed)
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        _ => "",
+    }
+}
+
+/// Defines the 'mock-server' command.
+pub fn mock_server_command() -> Command {
+    Command::new("mock-server")
+        .about("Run a mock OpenAI-compatible API server for demos and offline development")
+        .arg(
+            Arg::new("port")
+                .long("port")
+                .help("Port to listen on (0 picks a free one)")
+                .value_parser(clap::value_parser!(u16))
+                .default_value("8080"),
+        )
+        .arg(
+            Arg::new("host")
+                .long("host")
+                .help("Address to listen on")
+                .default_value("127.0.0.1"),
+        )
+        .arg(
+            Arg::new("script")
+                .long("script")
+                .help("YAML file of canned replies and fixtures; without one, questions are echoed back")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("fail")
+                .long("fail")
+                .value_name("STATUS")
+                .help("Answer requests with this error status, e.g. 429 or 500")
+                .value_parser(clap::value_parser!(u16).range(400..600)),
+        )
+        .arg(
+            Arg::new("fail-every")
+                .long("fail-every")
+                .value_name("N")
+                .help("Only fail every Nth request")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .default_value("1")
+                .requires("fail"),
+        )
+        .arg(
+            Arg::new("delay")
+                .long("delay")
+                .value_name("MS")
+                .help("Wait this many milliseconds before answering")
+                .value_parser(clap::value_parser!(u64)),
+        )
+}
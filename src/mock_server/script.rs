@@ -0,0 +1,163 @@
+//! What the mock server answers with: the question echoed back, canned replies
+//! matched by regex, or the contents of fixture files.
+//!
+//! A script is a YAML file:
+//!
+//! ```yaml
+//! rules:
+//!   - match: "(?i)weather"
+//!     reply: "It's sunny."
+//!   - match: "summari[sz]e"
+//!     fixture: fixtures/summary.json
+//!   - match: "overloaded"
+//!     status: 429
+//!   - match: "slowly"
+//!     reply: "Sorry for the wait."
+//!     delay_ms: 5000
+//! default: "I don't know."
+//! models: [gpt-4o-mini, mock-echo]
+//! ```
+
+use config::Config;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::error::{Error, Result};
+
+/// Model listed by `/v1/models` when the script doesn't name any.
+pub const DEFAULT_MODEL: &str = "samvada-mock";
+
+#[derive(Debug, Default, Deserialize)]
+struct ScriptFile {
+    #[serde(default)]
+    rules: Vec<RuleFile>,
+    #[serde(default)]
+    default: Option<String>,
+    #[serde(default)]
+    models: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RuleFile {
+    #[serde(rename = "match")]
+    pattern: String,
+    #[serde(default)]
+    reply: Option<String>,
+    /// A `.json` file holding a whole response body, or a text file holding the reply.
+    #[serde(default)]
+    fixture: Option<PathBuf>,
+    #[serde(default)]
+    status: Option<u16>,
+    #[serde(default)]
+    delay_ms: Option<u64>,
+}
+
+/// How to answer a chat request.
+#[derive(Debug, Clone)]
+pub enum Answer {
+    /// The question, echoed back.
+    Echo,
+    Text(String),
+    /// A complete response body from a `.json` fixture.
+    Body(Value),
+    /// An error response with this status.
+    Error(u16),
+}
+
+struct Rule {
+    regex: Regex,
+    answer: Answer,
+    delay: Option<Duration>,
+}
+
+/// The loaded script; without one every question is echoed back.
+#[derive(Default)]
+pub struct Script {
+    rules: Vec<Rule>,
+    default: Option<String>,
+    pub models: Vec<String>,
+}
+
+impl Script {
+    /// Loads a script, compiling its patterns and reading its fixtures up front.
+    ///
+    /// Fixture paths are relative to the script.
+    pub fn load(path: &Path) -> Result<Script> {
+        let file: ScriptFile = Config::builder()
+            .add_source(config::File::from(path))
+            .build()
+            .and_then(|config| config.try_deserialize())
+            .map_err(|e| Error::Config(format!("Failed to load {}: {}", path.display(), e)))?;
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+
+        let mut rules = Vec::new();
+        for rule in file.rules {
+            let regex = Regex::new(&rule.pattern).map_err(|e| {
+                Error::Config(format!(
+                    "Invalid pattern '{}' in {}: {}",
+                    rule.pattern,
+                    path.display(),
+                    e
+                ))
+            })?;
+            let answer = match (rule.reply, rule.fixture, rule.status) {
+                (Some(reply), None, None) => Answer::Text(reply),
+                (None, Some(fixture), None) => load_fixture(&base.join(fixture))?,
+                (None, None, Some(status)) => Answer::Error(status),
+                _ => {
+                    return Err(Error::Config(format!(
+                        "The rule for '{}' in {} needs exactly one of reply, fixture and status.",
+                        rule.pattern,
+                        path.display()
+                    )))
+                }
+            };
+            rules.push(Rule {
+                regex,
+                answer,
+                delay: rule.delay_ms.map(Duration::from_millis),
+            });
+        }
+
+        Ok(Script {
+            rules,
+            default: file.default,
+            models: file.models,
+        })
+    }
+
+    /// The answer to a question: the first matching rule's, else the default reply, else an echo.
+    pub fn answer(&self, question: &str) -> (Answer, Option<Duration>) {
+        if let Some(rule) = self.rules.iter().find(|rule| rule.regex.is_match(question)) {
+            return (rule.answer.clone(), rule.delay);
+        }
+        match &self.default {
+            Some(reply) => (Answer::Text(reply.clone()), None),
+            None => (Answer::Echo, None),
+        }
+    }
+}
+
+fn load_fixture(path: &Path) -> Result<Answer> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| Error::io(format!("Failed to read fixture {}", path.display()), e))?;
+    if path
+        .extension()
+        .is_some_and(|extension| extension == "json")
+    {
+        let body = serde_json::from_str(&content).map_err(|e| {
+            Error::Parse(format!(
+                "Fixture {} is not valid JSON: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        Ok(Answer::Body(body))
+    } else {
+        Ok(Answer::Text(content.trim_end().to_string()))
+    }
+}
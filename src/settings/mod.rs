@@ -0,0 +1,531 @@
+mod yaml;
+
+use clap::{Arg, ArgMatches, Command};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::chat::config::{
+    config_origin, ensure_config_exists, find_project_config, get_config_dir, get_env_file_path,
+    load_config_for, profile_flag, read_yaml_file, AppConfig, LEGACY_CONFIG_FILE_NAME,
+    PROJECT_CONFIG_FILE_NAME,
+};
+use crate::chat::lint::find_frontmatter;
+use crate::chat::lint::frontmatter::endpoint_problem;
+use crate::chat::lint::rules::unknown_rules;
+use crate::chat::parser::parse_frontmatter_entries;
+use crate::chat::personas::find_persona;
+use crate::error::{Error, Result};
+
+/// Frontmatter keys that override config keys, as `(frontmatter key, config key)`.
+const FRONTMATTER_OVERRIDES: &[(&str, &str)] = &[
+    ("system", "system_prompt"),
+    ("model", "model"),
+    ("api_endpoint", "api_endpoint"),
+];
+
+/// An effective configuration value and the layer it came from.
+struct Setting {
+    key: String,
+    value: Value,
+    origin: Option<String>,
+}
+
+/// A problem found by `config validate`.
+struct Problem {
+    error: bool,
+    message: String,
+}
+
+/// Handles the 'config' subcommand.
+pub fn handle_config_subcommand(matches: &ArgMatches) -> Result<()> {
+    match matches.subcommand() {
+        Some(("show", show_m)) => show(show_m),
+        Some(("get", get_m)) => get(get_m),
+        Some(("set", set_m)) => set_key(set_m),
+        Some(("unset", unset_m)) => unset_key(unset_m),
+        Some(("edit", edit_m)) => edit(edit_m),
+        Some(("validate", _)) => validate(),
+        Some(("path", _)) => {
+            path();
+            Ok(())
+        }
+        _ => {
+            println!("No valid config subcommand was used");
+            Ok(())
+        }
+    }
+}
+
+fn current_dir() -> PathBuf {
+    std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."))
+}
+
+/// Loads the effective settings for `start`.
+fn effective_settings(start: &Path) -> Result<Vec<Setting>> {
+    let config = load_config_for(start)?;
+    let value = serde_json::to_value(&config).unwrap_or(Value::Null);
+
+    let mut settings = Vec::new();
+    flatten(&value, String::new(), &mut settings);
+    apply_overrides(start, &config, &mut settings)
+        .map_err(|e| Error::Config(format!("Error resolving {}: {}", start.display(), e)))?;
+    Ok(settings)
+}
+
+/// Prints every effective setting, optionally with where it came from.
+fn show(matches: &ArgMatches) -> Result<()> {
+    let start = matches
+        .get_one::<String>("path")
+        .map(PathBuf::from)
+        .unwrap_or_else(current_dir);
+    let settings = effective_settings(&start)?;
+    print_settings(settings, &start, matches.get_flag("origin"));
+    Ok(())
+}
+
+fn print_settings(settings: Vec<Setting>, start: &Path, with_origin: bool) {
+    let project = find_project_config(start);
+    let width = settings
+        .iter()
+        .map(|setting| setting.key.len())
+        .max()
+        .unwrap_or(0);
+    for setting in settings {
+        let value = setting.value.to_string();
+        if with_origin {
+            let origin = setting
+                .origin
+                .unwrap_or_else(|| config_origin(&setting.key, project.as_deref()));
+            println!("{:<width$} = {}  # {}", setting.key, value, origin);
+        } else {
+            println!("{:<width$} = {}", setting.key, value);
+        }
+    }
+}
+
+/// Prints one effective value; a key with nested values prints all of them.
+fn get(matches: &ArgMatches) -> Result<()> {
+    let key = matches.get_one::<String>("key").unwrap();
+    let start = current_dir();
+    let settings = effective_settings(&start)?;
+
+    if let Some(setting) = settings.iter().find(|setting| &setting.key == key) {
+        match &setting.value {
+            Value::String(text) => println!("{}", text),
+            other => println!("{}", other),
+        }
+        return Ok(());
+    }
+
+    let prefix = format!("{}.", key);
+    let nested: Vec<Setting> = settings
+        .into_iter()
+        .filter(|setting| setting.key.starts_with(&prefix))
+        .collect();
+    if nested.is_empty() {
+        return Err(Error::Config(format!("'{}' is not set.", key)));
+    }
+    print_settings(nested, &start, matches.get_flag("origin"));
+    Ok(())
+}
+
+/// Returns the file `set`, `unset` and `edit` change: the global config, or with
+/// `--project` the nearest `.samvada.yaml` (a new one in the current directory if none).
+fn target_file(matches: &ArgMatches) -> Result<PathBuf> {
+    if matches.get_flag("project") {
+        return Ok(find_project_config(&current_dir())
+            .unwrap_or_else(|| current_dir().join(PROJECT_CONFIG_FILE_NAME)));
+    }
+    ensure_config_exists().map_err(|e| Error::io("Error locating config file", e))
+}
+
+fn set_key(matches: &ArgMatches) -> Result<()> {
+    let key = matches.get_one::<String>("key").unwrap();
+    let value = matches.get_one::<String>("value").unwrap();
+    let path: Vec<&str> = key.split('.').collect();
+    let file = target_file(matches)?;
+
+    let original = fs::read_to_string(&file).ok();
+    let updated = yaml::set_value(
+        original.as_deref().unwrap_or_default(),
+        &path,
+        &yaml::to_yaml_value(value),
+    )
+    .map_err(|e| Error::Config(format!("Can't set {}: {}", key, e)))?;
+    write_checked(&file, key, original.as_deref(), &updated)?;
+    println!("Set {} in {}", key, file.display());
+    Ok(())
+}
+
+fn unset_key(matches: &ArgMatches) -> Result<()> {
+    let key = matches.get_one::<String>("key").unwrap();
+    let path: Vec<&str> = key.split('.').collect();
+    let file = target_file(matches)?;
+
+    let original = fs::read_to_string(&file).unwrap_or_default();
+    let updated = yaml::unset_value(&original, &path)
+        .ok_or_else(|| Error::Config(format!("'{}' is not set in {}.", key, file.display())))?;
+    write_checked(&file, key, Some(&original), &updated)?;
+    println!("Removed {} from {}", key, file.display());
+    Ok(())
+}
+
+/// Writes `updated` to `file`, restoring `original` (`None` if the file didn't
+/// exist) if the configuration no longer loads.
+fn write_checked(file: &Path, key: &str, original: Option<&str>, updated: &str) -> Result<()> {
+    fs::write(file, updated)
+        .map_err(|e| Error::io(format!("Failed to write {}", file.display()), e))?;
+    if let Err(e) = load_config_for(&current_dir()) {
+        // Put back what was there so a typo can't leave samvada unusable
+        let restored = match original {
+            Some(original) => fs::write(file, original),
+            None => fs::remove_file(file),
+        };
+        if let Err(restore_error) = restored {
+            eprintln!("Failed to restore {}: {}", file.display(), restore_error);
+        }
+        return Err(Error::Config(format!(
+            "Not saved: changing '{}' would make the configuration invalid: {}",
+            key, e
+        )));
+    }
+    Ok(())
+}
+
+/// Opens the config file in `$VISUAL` or `$EDITOR`, then validates the result.
+fn edit(matches: &ArgMatches) -> Result<()> {
+    let file = target_file(matches)?;
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut words = editor.split_whitespace();
+    let program = words
+        .next()
+        .ok_or_else(|| Error::Config("$EDITOR is empty.".to_string()))?;
+
+    let status = std::process::Command::new(program)
+        .args(words)
+        .arg(&file)
+        .status()
+        .map_err(|e| Error::io(format!("Failed to start {}", editor), e))?;
+    if !status.success() {
+        return Err(Error::Config(format!("{} exited with {}", editor, status)));
+    }
+    validate()
+}
+
+/// Checks the config files against the `AppConfig` schema and reports problems.
+fn validate() -> Result<()> {
+    let start = current_dir();
+    let problems = config_problems(&start);
+
+    for problem in &problems {
+        let severity = if problem.error { "error" } else { "warning" };
+        eprintln!("{}: {}", severity, problem.message);
+    }
+    if problems.iter().any(|problem| problem.error) {
+        return Err(Error::Config("The configuration is invalid.".to_string()));
+    }
+    println!("Configuration is valid.");
+    Ok(())
+}
+
+fn config_problems(start: &Path) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let error = |message: String| Problem {
+        error: true,
+        message,
+    };
+    let warning = |message: String| Problem {
+        error: false,
+        message,
+    };
+
+    let global = match ensure_config_exists() {
+        Ok(global) => global,
+        Err(e) => return vec![error(format!("cannot create the global config: {}", e))],
+    };
+    if let Some(legacy) = global.parent().map(|dir| dir.join(LEGACY_CONFIG_FILE_NAME)) {
+        if legacy.is_file() {
+            problems.push(warning(format!(
+                "{} is ignored; move its settings into {}",
+                legacy.display(),
+                global.display()
+            )));
+        }
+    }
+
+    let files: Vec<PathBuf> = std::iter::once(global)
+        .chain(find_project_config(start))
+        .collect();
+    let mut layers = Vec::new();
+    for file in &files {
+        match read_yaml_file::<Value>(file) {
+            Ok(layer) => layers.push((file, layer)),
+            Err(e) => problems.push(error(format!("{}: {}", file.display(), e))),
+        }
+    }
+    if problems.iter().any(|problem| problem.error) {
+        return problems;
+    }
+
+    let config = match load_config_for(start) {
+        Ok(config) => config,
+        Err(e) => {
+            problems.push(error(format!("{} (in {})", e, describe_files(&files))));
+            return problems;
+        }
+    };
+
+    let known = serde_json::to_value(&config).unwrap_or(Value::Null);
+    for (file, layer) in &layers {
+        let mut unknown = Vec::new();
+        unknown_keys(layer, &known, String::new(), &mut unknown);
+        for key in unknown {
+            problems.push(warning(format!(
+                "{}: unknown key '{}' is ignored",
+                file.display(),
+                key
+            )));
+        }
+    }
+
+    for rule in unknown_rules(&config.lint) {
+        problems.push(warning(format!(
+            "unknown lint rule '{}' in lint.rules",
+            rule
+        )));
+    }
+    if let Some(problem) = endpoint_problem(&config.api_endpoint) {
+        problems.push(error(format!("api_endpoint {}", problem)));
+    }
+    for (name, profile) in &config.profiles {
+        if let Some(problem) = profile.api_endpoint.as_deref().and_then(endpoint_problem) {
+            problems.push(error(format!("profiles.{}.api_endpoint {}", name, problem)));
+        }
+    }
+    if let Some(name) = &config.profile {
+        if let Err(e) = config.find_profile(name) {
+            problems.push(error(format!("profile: {}", e)));
+        }
+    }
+
+    problems
+}
+
+fn describe_files(files: &[PathBuf]) -> String {
+    files
+        .iter()
+        .map(|file| file.display().to_string())
+        .collect::<Vec<_>>()
+        .join(" + ")
+}
+
+/// Collects keys set in `layer` that the loaded configuration doesn't have,
+/// i.e. keys that serde ignored.
+fn unknown_keys(layer: &Value, known: &Value, prefix: String, unknown: &mut Vec<String>) {
+    let (Value::Object(layer), Value::Object(known)) = (layer, known) else {
+        return;
+    };
+    for (key, value) in layer {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        match known.get(key) {
+            Some(known_value) => unknown_keys(value, known_value, path, unknown),
+            None => unknown.push(path),
+        }
+    }
+}
+
+/// Prints the locations of samvada's configuration files.
+fn path() {
+    let start = current_dir();
+    let describe = |path: Option<PathBuf>| {
+        path.map(|path| path.display().to_string())
+            .unwrap_or_else(|| "(none found)".to_string())
+    };
+    let config_dir = get_config_dir().ok();
+
+    println!("global config   {}", describe(ensure_config_exists().ok()));
+    println!("project config  {}", describe(find_project_config(&start)));
+    println!("env file        {}", describe(get_env_file_path().ok()));
+    println!(
+        "personas        {}",
+        describe(config_dir.as_ref().map(|dir| dir.join("personas")))
+    );
+    println!(
+        "templates       {}",
+        describe(config_dir.as_ref().map(|dir| dir.join("templates")))
+    );
+}
+
+/// Collects the leaves of `value` as dotted keys; lists and empty maps are leaves.
+fn flatten(value: &Value, prefix: String, settings: &mut Vec<Setting>) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, item) in map {
+                let key = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten(item, key, settings);
+            }
+        }
+        Value::Null => {}
+        _ => settings.push(Setting {
+            key: prefix,
+            value: value.clone(),
+            origin: None,
+        }),
+    }
+}
+
+/// Applies the active profile and, for a chat file, its persona and frontmatter
+/// on top of the config, in the same order as `chat ask`.
+fn apply_overrides(
+    path: &Path,
+    config: &AppConfig,
+    settings: &mut [Setting],
+) -> std::result::Result<(), String> {
+    let entries = if path.is_file() {
+        let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let lines: Vec<&str> = content.lines().collect();
+        match find_frontmatter(&lines) {
+            Some((start, end)) => parse_frontmatter_entries(&lines[start + 1..end], start + 1),
+            None => Vec::new(),
+        }
+    } else {
+        Vec::new()
+    };
+    let value_of = |key: &str| {
+        entries
+            .iter()
+            .find(|entry| entry.key == key && !entry.value.is_empty())
+    };
+
+    if profile_flag().is_none() {
+        if let Some(entry) = value_of("profile") {
+            apply_profile(settings, config, &entry.value, "frontmatter")?;
+        } else if let Some(name) = &config.profile {
+            apply_profile(settings, config, name, "config")?;
+        }
+    }
+
+    let persona = value_of("persona");
+    if let Some(entry) = persona {
+        let found = find_persona(&entry.value, &config.personas)?;
+        let origin = format!("persona {}", entry.value);
+        if let Some(system) = &found.system {
+            set(settings, "system_prompt", system, origin.clone());
+        }
+        if let Some(model) = &found.model {
+            set(settings, "model", model, origin);
+        }
+    }
+
+    for entry in &entries {
+        let Some((_, key)) = FRONTMATTER_OVERRIDES
+            .iter()
+            .find(|(frontmatter_key, _)| *frontmatter_key == entry.key)
+        else {
+            continue;
+        };
+        if persona.is_some() && entry.value.is_empty() {
+            continue;
+        }
+        let origin = format!("frontmatter {}:{}", path.display(), entry.line + 1);
+        set(settings, key, &entry.value, origin);
+    }
+
+    if let Some(name) = profile_flag() {
+        apply_profile(settings, config, name, "--profile")?;
+    }
+
+    Ok(())
+}
+
+fn apply_profile(
+    settings: &mut [Setting],
+    config: &AppConfig,
+    name: &str,
+    selected_by: &str,
+) -> std::result::Result<(), String> {
+    let profile = config.find_profile(name)?;
+    let origin = format!("profile {} (from {})", name, selected_by);
+    if let Some(api_endpoint) = &profile.api_endpoint {
+        set(settings, "api_endpoint", api_endpoint, origin.clone());
+    }
+    if let Some(model) = &profile.model {
+        set(settings, "model", model, origin);
+    }
+    Ok(())
+}
+
+fn set(settings: &mut [Setting], key: &str, value: &str, origin: String) {
+    if let Some(setting) = settings.iter_mut().find(|setting| setting.key == key) {
+        setting.value = Value::from(value);
+        setting.origin = Some(origin);
+    }
+}
+
+/// Defines the 'config' command for inspecting and changing samvada's configuration.
+pub fn config_command() -> Command {
+    let project_flag = Arg::new("project")
+        .long("project")
+        .help("Change the nearest .samvada.yaml instead of the global config")
+        .action(clap::ArgAction::SetTrue);
+    let origin_flag = Arg::new("origin")
+        .long("origin")
+        .help("Show which file, variable or frontmatter each value comes from")
+        .action(clap::ArgAction::SetTrue);
+
+    Command::new("config")
+        .about("Inspect and change samvada's configuration")
+        .subcommand(
+            Command::new("get")
+                .about("Print an effective value, e.g. `model` or `lint.rules`")
+                .arg(Arg::new("key").required(true).num_args(1))
+                .arg(origin_flag.clone()),
+        )
+        .subcommand(
+            Command::new("set")
+                .about("Set a value, keeping the file's comments")
+                .arg(Arg::new("key").required(true).num_args(1))
+                .arg(
+                    Arg::new("value")
+                        .required(true)
+                        .num_args(1)
+                        .allow_hyphen_values(true),
+                )
+                .arg(project_flag.clone()),
+        )
+        .subcommand(
+            Command::new("unset")
+                .about("Remove a value, keeping the file's comments")
+                .arg(Arg::new("key").required(true).num_args(1))
+                .arg(project_flag.clone()),
+        )
+        .subcommand(
+            Command::new("edit")
+                .about("Open the config file in $EDITOR")
+                .arg(project_flag),
+        )
+        .subcommand(Command::new("validate").about("Check the config files for errors"))
+        .subcommand(Command::new("path").about("Print the locations of the config files"))
+        .subcommand(
+            Command::new("show")
+                .visible_alias("list")
+                .about("Show the effective configuration")
+                .arg(
+                    Arg::new("path")
+                        .help("Chat file or directory to resolve the configuration for (default: current directory)")
+                        .num_args(1),
+                )
+                .arg(origin_flag),
+        )
+}
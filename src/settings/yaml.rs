@@ -0,0 +1,201 @@
+//! Line-based edits to YAML block mappings that keep comments and layout intact.
+
+use std::ops::Range;
+
+/// Sets the dotted `path` to `value` (already YAML), creating missing parent keys.
+///
+/// Fails if a parent key holds a value other than a mapping.
+pub fn set_value(content: &str, path: &[&str], value: &str) -> Result<String, String> {
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+    let mut range = 0..lines.len();
+    let mut parent_indent: Option<usize> = None;
+
+    for (depth, key) in path.iter().enumerate() {
+        let last = depth + 1 == path.len();
+        let Some(index) = find_child(&lines, range.clone(), key) else {
+            let indent = child_indent(&lines, range.clone())
+                .unwrap_or_else(|| parent_indent.map_or(0, |indent| indent + 2));
+            let new_lines = path[depth..].iter().enumerate().map(|(offset, key)| {
+                let pad = " ".repeat(indent + 2 * offset);
+                if depth + offset + 1 == path.len() {
+                    format!("{}{}: {}", pad, key, value)
+                } else {
+                    format!("{}{}:", pad, key)
+                }
+            });
+            let at = range.end;
+            lines.splice(at..at, new_lines);
+            return Ok(join(lines));
+        };
+
+        let indent = indent_of(&lines[index]);
+        let end = block_end(&lines, index);
+        let pad = " ".repeat(indent);
+        if last {
+            let comment = trailing_comment(&lines[index]);
+            lines[index] = format!("{}{}: {}{}", pad, key, value, comment);
+            lines.drain(index + 1..end);
+            return Ok(join(lines));
+        }
+
+        // Descend into the key, turning an inline value such as `{a: 1}` into a block
+        let inline = key_value(&lines[index]).map_or("", |(_, rest)| strip_comment(rest));
+        let mut end = end;
+        if !inline.is_empty() {
+            let block = flow_map_to_block(inline, indent + 2)
+                .ok_or_else(|| format!("'{}' holds '{}', not a mapping.", key, inline))?;
+            let comment = trailing_comment(&lines[index]);
+            lines[index] = format!("{}{}:{}", pad, key, comment);
+            end = index + 1 + block.len();
+            lines.splice(index + 1..index + 1, block);
+        }
+        range = index + 1..end;
+        parent_indent = Some(indent);
+    }
+
+    Ok(join(lines))
+}
+
+/// Re-emits an inline mapping such as `{temperature: 0}` as block lines at `indent`,
+/// or `None` if the value isn't a mapping.
+fn flow_map_to_block(inline: &str, indent: usize) -> Option<Vec<String>> {
+    match serde_yaml::from_str(inline).ok()? {
+        serde_yaml::Value::Null => Some(Vec::new()),
+        serde_yaml::Value::Mapping(map) if map.is_empty() => Some(Vec::new()),
+        serde_yaml::Value::Mapping(map) => {
+            let pad = " ".repeat(indent);
+            let block = serde_yaml::to_string(&map).ok()?;
+            Some(
+                block
+                    .lines()
+                    .map(|line| format!("{}{}", pad, line))
+                    .collect(),
+            )
+        }
+        _ => None,
+    }
+}
+
+/// Removes the dotted `path` and everything nested under it, or `None` if it isn't set.
+pub fn unset_value(content: &str, path: &[&str]) -> Option<String> {
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+    let mut range = 0..lines.len();
+    let mut found = None;
+
+    for key in path {
+        let index = find_child(&lines, range.clone(), key)?;
+        let end = block_end(&lines, index);
+        found = Some(index..end);
+        range = index + 1..end;
+    }
+
+    lines.drain(found?);
+    Some(join(lines))
+}
+
+/// Renders a command-line value as YAML, quoting it unless it reads back the
+/// same as a plain scalar (such as `gpt-4o`, `-1` or `true`), or is a flow
+/// list/map such as `[a, b]`.
+pub fn to_yaml_value(value: &str) -> String {
+    let trimmed = value.trim();
+    let flow = (trimmed.starts_with('[') && trimmed.ends_with(']'))
+        || (trimmed.starts_with('{') && trimmed.ends_with('}'));
+    let plain = !trimmed.is_empty()
+        && trimmed == value
+        && !value.contains('\n')
+        && match serde_yaml::from_str(value) {
+            Ok(serde_yaml::Value::String(parsed)) => parsed == value,
+            Ok(serde_yaml::Value::Number(_) | serde_yaml::Value::Bool(_)) => true,
+            Ok(serde_yaml::Value::Null) => matches!(value, "~" | "null" | "Null" | "NULL"),
+            _ => false,
+        };
+    if flow || plain {
+        value.to_string()
+    } else {
+        serde_json::to_string(value).unwrap_or_default()
+    }
+}
+
+fn join(lines: Vec<String>) -> String {
+    let mut content = lines.join("\n");
+    content.push('\n');
+    content
+}
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+fn is_blank_or_comment(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.is_empty() || trimmed.starts_with('#')
+}
+
+/// Splits a `key: value` line into its key and the rest, ignoring list items.
+fn key_value(line: &str) -> Option<(&str, &str)> {
+    let trimmed = line.trim_start();
+    if trimmed.starts_with('#') || trimmed.starts_with('-') {
+        return None;
+    }
+    let (key, rest) = trimmed.split_once(':')?;
+    if !(rest.is_empty() || rest.starts_with(' ')) {
+        return None;
+    }
+    Some((key.trim().trim_matches(|c| c == '"' || c == '\''), rest))
+}
+
+/// Drops a trailing `# comment` from an unquoted value.
+fn strip_comment(rest: &str) -> &str {
+    match rest.find(" #") {
+        Some(index) if !rest.contains(['"', '\'']) => rest[..index].trim(),
+        _ => rest.trim(),
+    }
+}
+
+/// Returns the `  # comment` ending a key line, so a new value can keep it.
+fn trailing_comment(line: &str) -> String {
+    let Some((_, rest)) = key_value(line) else {
+        return String::new();
+    };
+    match rest.find(" #") {
+        Some(index) if !rest.contains(['"', '\'']) => format!(" {}", rest[index..].trim()),
+        _ => String::new(),
+    }
+}
+
+/// Indentation of the first key inside `range`.
+fn child_indent(lines: &[String], range: Range<usize>) -> Option<usize> {
+    lines[range]
+        .iter()
+        .find(|line| key_value(line).is_some())
+        .map(|line| indent_of(line))
+}
+
+/// Finds `key` among the direct children of the block spanning `range`.
+fn find_child(lines: &[String], range: Range<usize>, key: &str) -> Option<usize> {
+    let indent = child_indent(lines, range.clone())?;
+    range.into_iter().find(|&index| {
+        indent_of(&lines[index]) == indent
+            && key_value(&lines[index]).is_some_and(|(found, _)| found == key)
+    })
+}
+
+/// Returns the index after the last line nested under the key on line `index`.
+fn block_end(lines: &[String], index: usize) -> usize {
+    let indent = indent_of(&lines[index]);
+    let mut end = index + 1;
+    while end < lines.len() {
+        let line = &lines[end];
+        let nested = indent_of(line) > indent
+            || (indent_of(line) == indent && line.trim_start().starts_with("- "));
+        if !(nested || is_blank_or_comment(line)) {
+            break;
+        }
+        end += 1;
+    }
+    // Blank lines and comments before the next key belong to that key
+    while end > index + 1 && is_blank_or_comment(&lines[end - 1]) {
+        end -= 1;
+    }
+    end
+}
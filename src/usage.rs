@@ -0,0 +1,409 @@
+use chrono::{Datelike, NaiveDate};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use serde_json::json;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::chat::budget::{print_budgets, statuses_for, BudgetStatus};
+use crate::chat::config::{load_config, load_config_for, profile_flag};
+use crate::chat::lint::find_frontmatter;
+use crate::chat::lint::walk::{collect_markdown_files, is_chat_file};
+use crate::chat::parser::{parse_frontmatter_entries, parse_response_metadata, parse_tags};
+use crate::chat::usage::{self, estimate_cost, project_dir, UsageEntry};
+use crate::error::{Error, Result};
+
+/// Handles the 'usage' command: reports from the ledger, or backfills it.
+pub fn handle_usage_subcommand(matches: &ArgMatches) -> Result<()> {
+    match matches.subcommand() {
+        Some(("backfill", backfill_m)) => backfill(backfill_m),
+        _ => report(matches),
+    }
+}
+
+/// Token and cost totals for one group of entries.
+#[derive(Default)]
+struct Totals {
+    requests: u64,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    total_tokens: u64,
+    cost: Option<f64>,
+}
+
+impl Totals {
+    fn add(&mut self, entry: &UsageEntry) {
+        self.requests += 1;
+        self.prompt_tokens += entry.prompt_tokens.unwrap_or(0);
+        self.completion_tokens += entry.completion_tokens.unwrap_or(0);
+        self.total_tokens += entry.total_tokens;
+        if let Some(cost) = entry.cost {
+            *self.cost.get_or_insert(0.0) += cost;
+        }
+    }
+
+    fn cost_text(&self) -> String {
+        self.cost
+            .map(|cost| format!("${:.4}", cost))
+            .unwrap_or_else(|| "-".to_string())
+    }
+}
+
+fn report(matches: &ArgMatches) -> Result<()> {
+    let by = matches.get_one::<String>("by").unwrap();
+    let since = date_arg(matches, "since")?;
+    let until = date_arg(matches, "until")?;
+
+    let entries = usage::load().map_err(|e| Error::io("Failed to read the usage ledger", e))?;
+    let mut groups: BTreeMap<String, Totals> = BTreeMap::new();
+    let mut total = Totals::default();
+    for entry in &entries {
+        let date = entry.timestamp().map(|timestamp| timestamp.date_naive());
+        if since.is_some_and(|since| date.is_none_or(|date| date < since))
+            || until.is_some_and(|until| date.is_none_or(|date| date > until))
+        {
+            continue;
+        }
+        for key in group_keys(entry, by) {
+            groups.entry(key).or_default().add(entry);
+        }
+        total.add(entry);
+    }
+
+    // The budgets that apply to requests from the current directory
+    let profile = profile_flag()
+        .map(str::to_string)
+        .or_else(|| load_config().ok().and_then(|config| config.profile));
+    let budgets = statuses_for(None, profile.as_deref())?;
+
+    match matches.get_one::<String>("format").unwrap().as_str() {
+        "json" => print_json(by, &groups, &total, &budgets),
+        "csv" => print_csv(by, &groups),
+        _ => {
+            print_table(by, &groups, &total);
+            print_budgets(&budgets);
+        }
+    }
+    Ok(())
+}
+
+fn date_arg(matches: &ArgMatches, name: &str) -> Result<Option<NaiveDate>> {
+    matches
+        .get_one::<String>(name)
+        .map(|value| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| {
+                Error::Usage(format!("Invalid --{} '{}'; use YYYY-MM-DD.", name, value))
+            })
+        })
+        .transpose()
+}
+
+/// The groups an entry counts towards; an entry with several tags counts towards each.
+fn group_keys(entry: &UsageEntry, by: &str) -> Vec<String> {
+    let timestamp = entry.timestamp();
+    let by_date = |format: &str| {
+        timestamp
+            .map(|timestamp| timestamp.format(format).to_string())
+            .unwrap_or_else(|| "(unknown)".to_string())
+    };
+    match by {
+        "week" => vec![timestamp
+            .map(|timestamp| {
+                let week = timestamp.iso_week();
+                format!("{}-W{:02}", week.year(), week.week())
+            })
+            .unwrap_or_else(|| "(unknown)".to_string())],
+        "month" => vec![by_date("%Y-%m")],
+        "model" => vec![entry.model.clone()],
+        "project" => vec![entry
+            .project
+            .clone()
+            .unwrap_or_else(|| "(none)".to_string())],
+        "tag" if entry.tags.is_empty() => vec!["(untagged)".to_string()],
+        "tag" => entry.tags.clone(),
+        _ => vec![by_date("%Y-%m-%d")],
+    }
+}
+
+fn print_table(by: &str, groups: &BTreeMap<String, Totals>, total: &Totals) {
+    if groups.is_empty() {
+        println!("No usage recorded yet.");
+        return;
+    }
+    let width = groups
+        .keys()
+        .map(String::len)
+        .chain([by.len(), "TOTAL".len()])
+        .max()
+        .unwrap_or(0);
+    println!(
+        "{:<width$}  {:>8}  {:>10}  {:>10}  {:>10}  {:>10}",
+        by.to_uppercase(),
+        "REQUESTS",
+        "PROMPT",
+        "COMPLETION",
+        "TOTAL",
+        "COST"
+    );
+    let rows = groups
+        .iter()
+        .map(|(key, totals)| (key.as_str(), totals))
+        .chain([("TOTAL", total)]);
+    for (key, totals) in rows {
+        println!(
+            "{:<width$}  {:>8}  {:>10}  {:>10}  {:>10}  {:>10}",
+            key,
+            totals.requests,
+            totals.prompt_tokens,
+            totals.completion_tokens,
+            totals.total_tokens,
+            totals.cost_text()
+        );
+    }
+}
+
+fn print_csv(by: &str, groups: &BTreeMap<String, Totals>) {
+    println!(
+        "{},requests,prompt_tokens,completion_tokens,total_tokens,cost",
+        by
+    );
+    for (key, totals) in groups {
+        println!(
+            "{},{},{},{},{},{}",
+            csv_field(key),
+            totals.requests,
+            totals.prompt_tokens,
+            totals.completion_tokens,
+            totals.total_tokens,
+            totals
+                .cost
+                .map(|cost| format!("{:.6}", cost))
+                .unwrap_or_default()
+        );
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn print_json(
+    by: &str,
+    groups: &BTreeMap<String, Totals>,
+    total: &Totals,
+    budgets: &[BudgetStatus],
+) {
+    let row = |totals: &Totals| {
+        json!({
+            "requests": totals.requests,
+            "prompt_tokens": totals.prompt_tokens,
+            "completion_tokens": totals.completion_tokens,
+            "total_tokens": totals.total_tokens,
+            "cost": totals.cost,
+        })
+    };
+    let rows: Vec<_> = groups
+        .iter()
+        .map(|(key, totals)| {
+            let mut value = row(totals);
+            value[by] = json!(key);
+            value
+        })
+        .collect();
+    let budgets: Vec<_> = budgets
+        .iter()
+        .map(|status| {
+            json!({
+                "period": status.period,
+                "scope": status.scope,
+                "unit": if status.is_cost { "usd" } else { "tokens" },
+                "used": status.used,
+                "cap": status.cap,
+                "remaining": (status.cap - status.used).max(0.0),
+            })
+        })
+        .collect();
+    let document = json!({
+        "group_by": by,
+        "rows": rows,
+        "total": row(total),
+        "budgets": budgets,
+    });
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&document).unwrap_or_default()
+    );
+}
+
+/// Adds the answers recorded in chat files' metadata comments to the ledger.
+fn backfill(matches: &ArgMatches) -> Result<()> {
+    let existing = usage::load().map_err(|e| Error::io("Failed to read the usage ledger", e))?;
+    let mut seen: HashSet<String> = existing.iter().map(entry_key).collect();
+
+    let mut added = Vec::new();
+    for path in matches.get_many::<String>("path").unwrap() {
+        for file in chat_files(Path::new(path))? {
+            for entry in entries_from_chat(&file) {
+                if seen.insert(entry_key(&entry)) {
+                    added.push(entry);
+                }
+            }
+        }
+    }
+
+    if added.is_empty() {
+        println!("Found no answers that aren't in the ledger yet");
+        return Ok(());
+    }
+    let ledger =
+        usage::append(&added).map_err(|e| Error::io("Failed to write the usage ledger", e))?;
+    println!("Added {} answers to {}", added.len(), ledger.display());
+    Ok(())
+}
+
+/// Identifies an answer: its response id, else its chat, date and token count.
+fn entry_key(entry: &UsageEntry) -> String {
+    match &entry.id {
+        Some(id) if !id.is_empty() => id.clone(),
+        _ => format!(
+            "{}|{}|{}",
+            entry.chat.as_deref().unwrap_or_default(),
+            entry.date,
+            entry.total_tokens
+        ),
+    }
+}
+
+fn chat_files(path: &Path) -> Result<Vec<PathBuf>> {
+    if path.is_file() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    if !path.is_dir() {
+        return Err(Error::Usage(format!(
+            "Invalid path {}: Not a file or directory.",
+            path.display()
+        )));
+    }
+    Ok(collect_markdown_files(path, &[], &[])
+        .map_err(Error::Usage)?
+        .into_iter()
+        .filter(|file| {
+            fs::read_to_string(file)
+                .map(|content| is_chat_file(&content))
+                .unwrap_or(false)
+        })
+        .collect())
+}
+
+/// Builds ledger entries from the metadata comments of a chat file.
+fn entries_from_chat(file: &Path) -> Vec<UsageEntry> {
+    let Ok(content) = fs::read_to_string(file) else {
+        return Vec::new();
+    };
+    let config = load_config_for(file).ok();
+    let lines: Vec<&str> = content.lines().collect();
+    let frontmatter = find_frontmatter(&lines)
+        .map(|(start, end)| parse_frontmatter_entries(&lines[start + 1..end], start + 1))
+        .unwrap_or_default();
+    let value_of = |key: &str| {
+        frontmatter
+            .iter()
+            .find(|entry| entry.key == key && !entry.value.is_empty())
+            .map(|entry| entry.value.clone())
+    };
+    let endpoint = value_of("api_endpoint")
+        .or_else(|| config.as_ref().map(|config| config.api_endpoint.clone()))
+        .unwrap_or_default();
+    let tags = value_of("tags")
+        .map(|tags| parse_tags(&tags))
+        .unwrap_or_default();
+    let chat = file.canonicalize().unwrap_or_else(|_| file.to_path_buf());
+    let project = project_dir(file).map(|dir| dir.display().to_string());
+
+    parse_response_metadata(&content)
+        .into_iter()
+        // Cached answers were counted when they were first received
+        .filter(|metadata| metadata.from_cache.is_none())
+        .filter_map(|metadata| {
+            let model = Some(metadata.model.clone())
+                .filter(|model| !model.is_empty())
+                .or_else(|| value_of("model"))?;
+            let date = metadata.created()?;
+            // Answers written since costs were recorded carry their own estimate
+            let cost = metadata.cost.or_else(|| {
+                config.as_ref().and_then(|config| {
+                    estimate_cost(
+                        config,
+                        &model,
+                        metadata.prompt_tokens,
+                        metadata.completion_tokens,
+                    )
+                })
+            });
+            Some(UsageEntry {
+                date: date.to_rfc3339(),
+                chat: Some(chat.display().to_string()),
+                project: project.clone(),
+                tags: tags.clone(),
+                profile: value_of("profile"),
+                cost,
+                model,
+                endpoint: endpoint.clone(),
+                id: Some(metadata.id).filter(|id| !id.is_empty()),
+                prompt_tokens: metadata.prompt_tokens,
+                completion_tokens: metadata.completion_tokens,
+                total_tokens: metadata.total_tokens,
+                latency_ms: metadata.latency_ms,
+                backfilled: true,
+                estimated: false,
+            })
+        })
+        .collect()
+}
+
+/// Defines the 'usage' command for token and cost reports.
+pub fn usage_command() -> Command {
+    Command::new("usage")
+        .about("Report the tokens and estimated cost of answered requests")
+        .args_conflicts_with_subcommands(true)
+        .arg(
+            Arg::new("by")
+                .long("by")
+                .help("How to group the report")
+                .value_parser(["day", "week", "month", "model", "project", "tag"])
+                .default_value("day"),
+        )
+        .arg(
+            Arg::new("since")
+                .long("since")
+                .help("Only count requests on or after this date (YYYY-MM-DD)")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("until")
+                .long("until")
+                .help("Only count requests on or before this date (YYYY-MM-DD)")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .help("Output format")
+                .value_parser(["table", "csv", "json"])
+                .default_value("table"),
+        )
+        .subcommand(
+            Command::new("backfill")
+                .about("Add answers from chat files' metadata comments to the ledger")
+                .arg(
+                    Arg::new("path")
+                        .help("Chat files, or directories to search for them")
+                        .required(true)
+                        .action(ArgAction::Append),
+                ),
+        )
+}